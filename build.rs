@@ -4,6 +4,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "proto/googleapis/google/datastore/v1/datastore.proto",
             "proto/googleapis/google/firestore/v1/firestore.proto",
             "proto/googleapis/google/pubsub/v1/pubsub.proto",
+            "proto/googleapis/google/pubsub/v1/schema.proto",
+            "proto/googleapis/google/rpc/error_details.proto",
         ],
         &["proto/googleapis"],
     )?;
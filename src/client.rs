@@ -0,0 +1,232 @@
+use crate::{
+    config,
+    recorder::MetricsRecorder,
+    retry::RetryPolicy,
+    service::{
+        auth::{self, SharedToken, TokenCache, TokenProvider},
+        emulator_host_from_env, ChannelSettings, Credentials, EndpointSettings, Service, Transport,
+    },
+    Error,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::Mutex;
+
+/// Settings for a [`Client`].
+#[derive(Clone)]
+pub struct ClientConfig {
+    project_id: String,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    emulator_hosts: HashMap<Service, String>,
+    retry_policy: RetryPolicy,
+    channel_settings: ChannelSettings,
+    endpoint_settings: HashMap<Service, EndpointSettings>,
+    metrics_recorder: Option<Arc<dyn MetricsRecorder>>,
+}
+
+impl ClientConfig {
+    pub fn new(project_id: impl Into<String>) -> ClientConfig {
+        ClientConfig {
+            project_id: project_id.into(),
+            token_provider: None,
+            emulator_hosts: HashMap::new(),
+            retry_policy: RetryPolicy::default(),
+            channel_settings: ChannelSettings::default(),
+            endpoint_settings: HashMap::new(),
+            metrics_recorder: None,
+        }
+    }
+
+    /// Authenticates the requests of the client with `provider` instead of the default credentials.
+    pub fn token_provider(mut self, provider: impl TokenProvider + 'static) -> ClientConfig {
+        self.token_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Connects `service` to an emulator at `host`, such as `localhost:8085`, over plaintext and
+    /// without credentials.
+    ///
+    /// Without this, the emulator host environment variable of the service is honored.
+    pub fn emulator_host(mut self, service: Service, host: impl Into<String>) -> ClientConfig {
+        self.emulator_hosts.insert(service, host.into());
+        self
+    }
+
+    /// Retries the idempotent RPCs of the client with `policy`.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> ClientConfig {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Configures the channels the client opens for each service.
+    pub fn channel_settings(mut self, settings: ChannelSettings) -> ClientConfig {
+        self.channel_settings = settings;
+        self
+    }
+
+    /// Connects `service` to another endpoint, such as a regional one, instead of its default
+    /// domain. The other services of the client keep their own settings.
+    pub fn endpoint_settings(
+        mut self,
+        service: Service,
+        settings: EndpointSettings,
+    ) -> ClientConfig {
+        self.endpoint_settings.insert(service, settings);
+        self
+    }
+
+    /// Records the metrics of the client with `recorder` instead of the global recorder.
+    pub fn metrics_recorder(mut self, recorder: Arc<dyn MetricsRecorder>) -> ClientConfig {
+        self.metrics_recorder = Some(recorder);
+        self
+    }
+}
+
+/// A connection to the services of a single project.
+///
+/// Channels are opened lazily per service and shared by the clones of a `Client`, so it is cheap
+/// to clone. Unlike `grpc_gcp::init`, any number of clients can be used in one process.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: ClientConfig,
+    tokens: Option<Arc<TokenCache>>,
+    /// The tokens of each service, held so that requests skip the cache.
+    shared_tokens: RwLock<HashMap<Service, Arc<SharedToken>>>,
+    channels: Mutex<HashMap<Service, Transport>>,
+}
+
+impl Client {
+    pub fn new(config: ClientConfig) -> Client {
+        let tokens = config
+            .token_provider
+            .clone()
+            .map(|provider| Arc::new(TokenCache::new(provider)));
+        Client {
+            inner: Arc::new(Inner {
+                config,
+                tokens,
+                shared_tokens: RwLock::new(HashMap::new()),
+                channels: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    pub fn project_id(&self) -> &str {
+        &self.inner.config.project_id
+    }
+
+    pub(crate) fn retry_policy(&self) -> &RetryPolicy {
+        &self.inner.config.retry_policy
+    }
+
+    pub(crate) fn metrics_recorder(&self) -> Option<&Arc<dyn MetricsRecorder>> {
+        self.inner.config.metrics_recorder.as_ref()
+    }
+
+    fn emulator_host(&self, service: Service) -> Option<String> {
+        self.inner
+            .config
+            .emulator_hosts
+            .get(&service)
+            .cloned()
+            .or_else(|| emulator_host_from_env(service.emulator_host_var()))
+    }
+
+    pub(crate) async fn channel(&self, service: Service) -> Result<Transport, Error> {
+        let mut channels = self.inner.channels.lock().await;
+        if let Some(channel) = channels.get(&service) {
+            return Ok(channel.clone());
+        }
+        let emulator_host = self.emulator_host(service);
+        let config = &self.inner.config;
+        let endpoint_settings = config.endpoint_settings.get(&service).cloned();
+        let channel = crate::service::create_channel(
+            service.domain(),
+            emulator_host,
+            &endpoint_settings.unwrap_or_default(),
+            &config.channel_settings,
+            config.metrics_recorder.clone(),
+        )
+        .await?;
+        channels.insert(service, channel.clone());
+        Ok(channel)
+    }
+
+    pub(crate) async fn credentials(
+        &self,
+        service: Service,
+        scopes: &[&str],
+    ) -> Result<Credentials, auth::Error> {
+        if self.emulator_host(service).is_some() {
+            return Ok(Credentials::Emulator);
+        }
+        let held = self
+            .inner
+            .shared_tokens
+            .read()
+            .unwrap()
+            .get(&service)
+            .cloned();
+        if let Some(shared) = held {
+            shared.ensure().await?;
+            return Ok(Credentials::Token(shared));
+        }
+        let cache = match &self.inner.tokens {
+            Some(cache) => cache.clone(),
+            None => auth::default_token_cache(),
+        };
+        let shared = cache.get(scopes).await?;
+        let mut shared_tokens = self.inner.shared_tokens.write().unwrap();
+        shared_tokens.insert(service, shared.clone());
+        Ok(Credentials::Token(shared))
+    }
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("project_id", &self.project_id())
+            .finish()
+    }
+}
+
+/// The project of `client`, or the one set with `grpc_gcp::init`.
+pub(crate) fn project_id(client: Option<&Client>) -> &str {
+    match client {
+        Some(client) => client.project_id(),
+        None => config::project_id(),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{Client, ClientConfig};
+    use crate::{Error, Service};
+
+    #[tokio::test]
+    async fn test_emulator_host() {
+        let client = Client::new(
+            ClientConfig::new("project").emulator_host(Service::PubSub, "localhost:8085"),
+        );
+        assert_eq!(
+            Some("localhost:8085".to_string()),
+            client.emulator_host(Service::PubSub)
+        );
+        assert!(client.channel(Service::PubSub).await.is_ok());
+
+        let client =
+            Client::new(ClientConfig::new("project").emulator_host(Service::PubSub, "local host"));
+        match client.channel(Service::PubSub).await {
+            Err(Error::InvalidEndpoint(_)) => {}
+            _ => panic!("The emulator host should be invalid"),
+        }
+    }
+}
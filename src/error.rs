@@ -0,0 +1,219 @@
+use crate::{
+    retry::CallError,
+    serde_properties,
+    service::{auth, google::datastore::v1::Key},
+    ErrorDetails,
+};
+use std::sync::Arc;
+use tonic::Code;
+
+/// The error of every service of the crate.
+#[derive(Debug, Clone)]
+pub enum Error {
+    Auth(Arc<auth::Error>),
+    Transport(Arc<tonic::transport::Error>),
+    /// The status the server failed the call with, boxed to keep `Result<_, Error>` small.
+    Status(Box<tonic::Status>),
+    /// The deadline set with `CallOptions::deadline` has passed.
+    Timeout,
+    Deserialize(serde_properties::deserializer::Error),
+    Json(Arc<serde_json::Error>),
+    /// The Datastore entity of the key does not exist.
+    NotFound(Key),
+    /// The Pub/Sub publisher has already been shut down.
+    Closed,
+    OrderingKeyPaused(String),
+    Schema(String),
+    /// A [`Value`](crate::Value) of a type that the database cannot hold, such as a reference in
+    /// Datastore.
+    UnsupportedValue(String),
+    InvalidPush(String),
+    Unauthorized(String),
+    /// The address of an endpoint or an emulator is not a valid URI authority.
+    InvalidEndpoint(String),
+}
+
+/// The broad category of an [`Error`], which is the same whichever service it comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    /// The call conflicted with another one, such as a concurrent transaction.
+    Aborted,
+    Unavailable,
+    InvalidArgument,
+    /// The call did not complete before its deadline.
+    DeadlineExceeded,
+    /// No credentials could be obtained, or the server did not accept them.
+    Auth,
+    Transport,
+    /// A value could not be converted from or into its serialized form.
+    Serde,
+    Other,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Auth(_) | Error::Unauthorized(_) => ErrorKind::Auth,
+            Error::Transport(_) => ErrorKind::Transport,
+            Error::Status(status) => match status.code() {
+                Code::NotFound => ErrorKind::NotFound,
+                Code::AlreadyExists => ErrorKind::AlreadyExists,
+                Code::PermissionDenied => ErrorKind::PermissionDenied,
+                Code::Unauthenticated => ErrorKind::Auth,
+                Code::Aborted => ErrorKind::Aborted,
+                Code::Unavailable => ErrorKind::Unavailable,
+                Code::InvalidArgument | Code::OutOfRange => ErrorKind::InvalidArgument,
+                Code::DeadlineExceeded => ErrorKind::DeadlineExceeded,
+                _ => ErrorKind::Other,
+            },
+            Error::Timeout => ErrorKind::DeadlineExceeded,
+            Error::Deserialize(_) | Error::Json(_) => ErrorKind::Serde,
+            Error::NotFound(_) => ErrorKind::NotFound,
+            Error::Schema(_)
+            | Error::UnsupportedValue(_)
+            | Error::InvalidPush(_)
+            | Error::InvalidEndpoint(_) => ErrorKind::InvalidArgument,
+            Error::Closed | Error::OrderingKeyPaused(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Whether the same call may succeed when it is sent again.
+    ///
+    /// Only idempotent calls should be retried, because a failed call may have been applied.
+    pub fn is_retryable(&self) -> bool {
+        let exhausted =
+            matches!(self, Error::Status(status) if status.code() == Code::ResourceExhausted);
+        exhausted
+            || matches!(
+                self.kind(),
+                ErrorKind::Unavailable
+                    | ErrorKind::Aborted
+                    | ErrorKind::DeadlineExceeded
+                    | ErrorKind::Transport
+            )
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound
+    }
+
+    /// The details the server attached to a failed call, such as the reason of the failure or
+    /// the fields of the request that are invalid.
+    pub fn details(&self) -> Option<ErrorDetails> {
+        match self {
+            Error::Status(status) => Some(ErrorDetails::from_status(status)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Auth(e) => e.fmt(f),
+            Error::Transport(e) => e.fmt(f),
+            Error::Status(e) => e.fmt(f),
+            Error::Timeout => write!(f, "The deadline of the call has passed"),
+            Error::Deserialize(e) => e.fmt(f),
+            Error::Json(e) => e.fmt(f),
+            Error::NotFound(key) => write!(f, "Not Found: {:?}", key),
+            Error::Closed => write!(f, "The publisher has already been shut down"),
+            Error::OrderingKeyPaused(key) => write!(
+                f,
+                "Publishing with the ordering key {:?} is paused by a previous failure. Call resume_publish to resume it.",
+                key
+            ),
+            Error::Schema(message) => write!(f, "{}", message),
+            Error::UnsupportedValue(name) => {
+                write!(f, "{} values are not supported by the database", name)
+            }
+            Error::InvalidPush(message) => write!(f, "Invalid push request: {}", message),
+            Error::Unauthorized(message) => write!(f, "Unauthorized push request: {}", message),
+            Error::InvalidEndpoint(message) => write!(f, "Invalid endpoint: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Auth(e) => Some(e.as_ref()),
+            Error::Transport(e) => Some(e.as_ref()),
+            Error::Status(e) => Some(e.as_ref()),
+            Error::Deserialize(e) => Some(e),
+            Error::Json(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<auth::Error> for Error {
+    fn from(err: auth::Error) -> Self {
+        Error::Auth(Arc::new(err))
+    }
+}
+
+impl From<tonic::transport::Error> for Error {
+    fn from(err: tonic::transport::Error) -> Self {
+        Error::Transport(Arc::new(err))
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(err: tonic::Status) -> Self {
+        Error::Status(Box::new(err))
+    }
+}
+
+impl From<CallError> for Error {
+    fn from(err: CallError) -> Self {
+        match err {
+            CallError::Status(status) => Error::Status(status),
+            CallError::Timeout => Error::Timeout,
+        }
+    }
+}
+
+impl From<serde_properties::deserializer::Error> for Error {
+    fn from(err: serde_properties::deserializer::Error) -> Self {
+        Error::Deserialize(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(Arc::new(err))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind};
+    use std::error::Error as _;
+    use tonic::Status;
+
+    #[test]
+    fn test_kind() {
+        let not_found = Error::from(Status::not_found("missing"));
+        assert_eq!(ErrorKind::NotFound, not_found.kind());
+        assert!(not_found.is_not_found());
+        assert!(!not_found.is_retryable());
+        assert!(not_found.source().is_some());
+
+        assert!(Error::from(Status::unavailable("")).is_retryable());
+        assert!(Error::from(Status::resource_exhausted("")).is_retryable());
+        assert!(Error::Timeout.is_retryable());
+        assert_eq!(
+            ErrorKind::Auth,
+            Error::from(Status::unauthenticated("")).kind()
+        );
+        let json = serde_json::from_str::<u8>("x").unwrap_err();
+        assert_eq!(ErrorKind::Serde, Error::from(json).kind());
+        assert!(!Error::Closed.is_retryable());
+    }
+}
@@ -0,0 +1,270 @@
+//! Typed access to the details that Google APIs attach to a failed call.
+
+use crate::{proto::google::rpc, util::time::from_proto_duration};
+use prost::Message;
+use std::{collections::HashMap, time::Duration};
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/google.rpc.";
+
+/// The structured cause of an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorInfo {
+    /// A constant that identifies the cause within `domain`, such as `API_DISABLED`.
+    pub reason: String,
+    /// The service that generated the error, such as `pubsub.googleapis.com`.
+    pub domain: String,
+    pub metadata: HashMap<String, String>,
+}
+
+/// How long the client should wait before retrying.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryInfo {
+    pub retry_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaViolation {
+    /// The quota that was exceeded, such as `project:my-project`.
+    pub subject: String,
+    pub description: String,
+}
+
+/// The quota checks that failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaFailure {
+    pub violations: Vec<QuotaViolation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldViolation {
+    /// The path of the field in the request, such as `topic.labels`.
+    pub field: String,
+    pub description: String,
+}
+
+/// The fields of the request that are invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadRequest {
+    pub field_violations: Vec<FieldViolation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreconditionViolation {
+    /// A service specific kind of precondition, such as `TOS`.
+    pub violation_type: String,
+    pub subject: String,
+    pub description: String,
+}
+
+/// The preconditions of the request that were not met.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreconditionFailure {
+    pub violations: Vec<PreconditionViolation>,
+}
+
+/// The resource that the request accessed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceInfo {
+    pub resource_type: String,
+    pub resource_name: String,
+    pub owner: String,
+    pub description: String,
+}
+
+/// The details of a failed call, decoded from the `grpc-status-details-bin` trailer.
+///
+/// Every kind of detail is absent unless the server attached it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorDetails {
+    pub error_info: Option<ErrorInfo>,
+    pub retry_info: Option<RetryInfo>,
+    pub quota_failure: Option<QuotaFailure>,
+    pub bad_request: Option<BadRequest>,
+    pub precondition_failure: Option<PreconditionFailure>,
+    pub resource_info: Option<ResourceInfo>,
+}
+
+impl ErrorDetails {
+    /// Decodes the details of `status`. Details that cannot be decoded are ignored.
+    pub fn from_status(status: &tonic::Status) -> ErrorDetails {
+        let mut details = ErrorDetails::default();
+        let status = match rpc::Status::decode(status.details()) {
+            Ok(status) => status,
+            Err(_) => return details,
+        };
+        for any in status.details {
+            let name = match any.type_url.strip_prefix(TYPE_URL_PREFIX) {
+                Some(name) => name,
+                None => continue,
+            };
+            let value = any.value.as_slice();
+            match name {
+                "ErrorInfo" => details.error_info = decode(value, ErrorInfo::from_tonic),
+                "RetryInfo" => details.retry_info = decode(value, RetryInfo::from_tonic),
+                "QuotaFailure" => details.quota_failure = decode(value, QuotaFailure::from_tonic),
+                "BadRequest" => details.bad_request = decode(value, BadRequest::from_tonic),
+                "PreconditionFailure" => {
+                    details.precondition_failure = decode(value, PreconditionFailure::from_tonic)
+                }
+                "ResourceInfo" => details.resource_info = decode(value, ResourceInfo::from_tonic),
+                _ => {}
+            }
+        }
+        details
+    }
+}
+
+fn decode<P: Message + Default, T>(value: &[u8], from_tonic: impl FnOnce(P) -> T) -> Option<T> {
+    P::decode(value).ok().map(from_tonic)
+}
+
+impl ErrorInfo {
+    fn from_tonic(info: rpc::ErrorInfo) -> ErrorInfo {
+        ErrorInfo {
+            reason: info.reason,
+            domain: info.domain,
+            metadata: info.metadata,
+        }
+    }
+}
+
+impl RetryInfo {
+    fn from_tonic(info: rpc::RetryInfo) -> RetryInfo {
+        RetryInfo {
+            retry_delay: info.retry_delay.map(from_proto_duration),
+        }
+    }
+}
+
+impl QuotaFailure {
+    fn from_tonic(failure: rpc::QuotaFailure) -> QuotaFailure {
+        let violations = failure
+            .violations
+            .into_iter()
+            .map(|violation| QuotaViolation {
+                subject: violation.subject,
+                description: violation.description,
+            });
+        QuotaFailure {
+            violations: violations.collect(),
+        }
+    }
+}
+
+impl BadRequest {
+    fn from_tonic(request: rpc::BadRequest) -> BadRequest {
+        let violations = request
+            .field_violations
+            .into_iter()
+            .map(|violation| FieldViolation {
+                field: violation.field,
+                description: violation.description,
+            });
+        BadRequest {
+            field_violations: violations.collect(),
+        }
+    }
+}
+
+impl PreconditionFailure {
+    fn from_tonic(failure: rpc::PreconditionFailure) -> PreconditionFailure {
+        let violations = failure
+            .violations
+            .into_iter()
+            .map(|violation| PreconditionViolation {
+                violation_type: violation.r#type,
+                subject: violation.subject,
+                description: violation.description,
+            });
+        PreconditionFailure {
+            violations: violations.collect(),
+        }
+    }
+}
+
+impl ResourceInfo {
+    fn from_tonic(info: rpc::ResourceInfo) -> ResourceInfo {
+        ResourceInfo {
+            resource_type: info.resource_type,
+            resource_name: info.resource_name,
+            owner: info.owner,
+            description: info.description,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{BadRequest, ErrorDetails, FieldViolation, RetryInfo, TYPE_URL_PREFIX};
+    use crate::proto::google::rpc;
+    use prost::Message;
+    use std::time::Duration;
+    use tonic::{Code, Status};
+
+    fn any<M: Message>(name: &str, message: M) -> prost_types::Any {
+        let mut value = Vec::new();
+        message.encode(&mut value).unwrap();
+        prost_types::Any {
+            type_url: [TYPE_URL_PREFIX, name].concat(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_from_status() {
+        let details = rpc::Status {
+            code: Code::InvalidArgument as i32,
+            message: "invalid".into(),
+            details: vec![
+                any(
+                    "RetryInfo",
+                    rpc::RetryInfo {
+                        retry_delay: Some(prost_types::Duration {
+                            seconds: 2,
+                            nanos: 0,
+                        }),
+                    },
+                ),
+                any(
+                    "BadRequest",
+                    rpc::BadRequest {
+                        field_violations: vec![rpc::bad_request::FieldViolation {
+                            field: "topic.name".into(),
+                            description: "too long".into(),
+                        }],
+                    },
+                ),
+                prost_types::Any {
+                    type_url: "type.googleapis.com/google.rpc.Unknown".into(),
+                    value: vec![1, 2, 3],
+                },
+            ],
+        };
+        let mut bytes = Vec::new();
+        details.encode(&mut bytes).unwrap();
+        let status = Status::with_details(Code::InvalidArgument, "invalid", bytes.into());
+
+        let details = ErrorDetails::from_status(&status);
+        assert_eq!(
+            Some(RetryInfo {
+                retry_delay: Some(Duration::from_secs(2))
+            }),
+            details.retry_info
+        );
+        assert_eq!(
+            Some(BadRequest {
+                field_violations: vec![FieldViolation {
+                    field: "topic.name".into(),
+                    description: "too long".into(),
+                }]
+            }),
+            details.bad_request
+        );
+        assert_eq!(None, details.error_info);
+
+        let status = Status::new(Code::InvalidArgument, "invalid");
+        assert_eq!(ErrorDetails::default(), ErrorDetails::from_status(&status));
+    }
+}
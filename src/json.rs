@@ -0,0 +1,514 @@
+//! Conversion between entities or documents and `serde_json::Value`, enabled by the `json`
+//! feature.
+//!
+//! Null, booleans, strings, arrays and maps map to their JSON counterparts, and integers and
+//! doubles to numbers. Doubles that JSON cannot hold, such as NaN, become `null`. The other types
+//! are written as set in [`JsonOptions`]:
+//!
+//! | Type      | Default                                | Alternative                                |
+//! |-----------|----------------------------------------|--------------------------------------------|
+//! | Bytes     | base64 string                          | array of numbers                           |
+//! | Timestamp | RFC 3339 string                        | `{"seconds": 1, "nanos": 0}`               |
+//! | GeoPoint  | `{"latitude": 1.0, "longitude": 2.0}`  | `[1.0, 2.0]`                               |
+//! | Key       | path, such as `["Parent", "a", "Child", 1]` | `{"project_id", "namespace_id", "path"}` |
+//! | Reference | the name of the document               |                                            |
+//!
+//! JSON cannot tell these types from strings, arrays and maps, so they are read back as such,
+//! unless [`JsonOptions::tagged`] wraps each of them in an object with a single key that names
+//! its type, such as `{"$timestamp": "2021-01-01T00:00:00Z"}`. Tagged values convert back to the
+//! same entity or document.
+
+use crate::{
+    datastore,
+    proto::google::datastore::v1::{
+        key::{path_element::IdType, PathElement},
+        Key, PartitionId,
+    },
+    serde_properties::{ArrayValueTrait, LatLngTrait, MapValueTrait, ValueTrait, ValueType},
+    util::time::from_proto_timestamp,
+    Error, GeoPoint, Value,
+};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use serde::{de::Error as _, Deserialize, Serialize};
+use serde_json::{json, Map, Number, Value as Json};
+
+const TIMESTAMP_TAG: &str = "$timestamp";
+const BYTES_TAG: &str = "$bytes";
+const GEO_POINT_TAG: &str = "$geoPoint";
+const KEY_TAG: &str = "$key";
+const REFERENCE_TAG: &str = "$reference";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BytesFormat {
+    Base64,
+    Array,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestampFormat {
+    /// A string with as many digits of fractional seconds as needed, in UTC.
+    Rfc3339,
+    Object,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoPointFormat {
+    Object,
+    /// The latitude and the longitude.
+    Array,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyFormat {
+    /// The kind and the ID or name of each element of the path, with `null` for an element
+    /// without either. The partition is left out.
+    Path,
+    Object,
+}
+
+/// How the types without a JSON counterpart are converted.
+#[derive(Debug, Clone)]
+pub struct JsonOptions {
+    pub bytes: BytesFormat,
+    pub timestamps: TimestampFormat,
+    pub geo_points: GeoPointFormat,
+    pub keys: KeyFormat,
+    /// Whether to wrap bytes, timestamps, geopoints, keys and references in an object that names
+    /// their type, so that they are read back as the same type.
+    pub tagged: bool,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            bytes: BytesFormat::Base64,
+            timestamps: TimestampFormat::Rfc3339,
+            geo_points: GeoPointFormat::Object,
+            keys: KeyFormat::Path,
+            tagged: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TimestampObject {
+    seconds: i64,
+    nanos: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GeoPointObject {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyObject {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    namespace_id: Option<String>,
+    path: Vec<PathObject>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PathObject {
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+pub(crate) fn to_json<V: ValueTrait>(value: V, options: &JsonOptions) -> Json {
+    let value_type = match value.into_value_type() {
+        Some(value_type) => value_type,
+        None => return Json::Null,
+    };
+    match value_type {
+        ValueType::NullValue(_) => Json::Null,
+        ValueType::BooleanValue(value) => Json::Bool(value),
+        ValueType::IntegerValue(value) => Json::from(value),
+        ValueType::DoubleValue(value) => Number::from_f64(value).map_or(Json::Null, Json::Number),
+        ValueType::StringValue(value) => Json::String(value),
+        ValueType::TimestampValue(value) => {
+            let json = match options.timestamps {
+                TimestampFormat::Rfc3339 => match timestamp_to_rfc3339(&value) {
+                    Some(time) => Json::String(time),
+                    None => Json::Null,
+                },
+                TimestampFormat::Object => json!(TimestampObject {
+                    seconds: value.seconds,
+                    nanos: value.nanos,
+                }),
+            };
+            options.tag(TIMESTAMP_TAG, json)
+        }
+        ValueType::BytesValue(value) => {
+            let json = match options.bytes {
+                BytesFormat::Base64 => Json::String(base64::encode(value)),
+                BytesFormat::Array => Json::from(value),
+            };
+            options.tag(BYTES_TAG, json)
+        }
+        ValueType::ReferenceValue(value) => options.tag(REFERENCE_TAG, Json::String(value)),
+        ValueType::GeoPointValue(value) => {
+            let (latitude, longitude) = (value.get_latitude(), value.get_longitude());
+            let json = match options.geo_points {
+                GeoPointFormat::Object => json!(GeoPointObject {
+                    latitude,
+                    longitude
+                }),
+                GeoPointFormat::Array => json!([latitude, longitude]),
+            };
+            options.tag(GEO_POINT_TAG, json)
+        }
+        ValueType::KeyValue(value) => options.tag(KEY_TAG, key_to_json(value, options.keys)),
+        ValueType::ArrayValue(value) => {
+            let values = value.get_values().into_iter();
+            Json::Array(values.map(|value| to_json(value, options)).collect())
+        }
+        ValueType::MapValue(value) => {
+            let fields = value.get_fields().into_iter();
+            let fields = fields.map(|(name, value)| (name, to_json(value, options)));
+            Json::Object(fields.collect())
+        }
+    }
+}
+
+/// Fails when `json` holds a tagged value that the database cannot hold, or a tagged value that
+/// is not in the format set in `options`.
+pub(crate) fn from_json<V: ValueTrait>(json: Json, options: &JsonOptions) -> Result<V, Error> {
+    value_from_json(json, options)?.into_proto()
+}
+
+fn value_from_json(json: Json, options: &JsonOptions) -> Result<Value, Error> {
+    let value = match json {
+        Json::Null => Value::Null,
+        Json::Bool(value) => Value::Bool(value),
+        Json::Number(value) => match value.as_i64() {
+            Some(value) => Value::Integer(value),
+            None => Value::Double(value.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(value) => Value::String(value),
+        Json::Array(values) => {
+            let values = values
+                .into_iter()
+                .map(|value| value_from_json(value, options));
+            Value::Array(values.collect::<Result<_, _>>()?)
+        }
+        Json::Object(fields) => match options.untag(fields) {
+            Ok((tag, json)) => from_tagged(tag, json, options)?,
+            Err(fields) => {
+                let fields = fields
+                    .into_iter()
+                    .map(|(name, value)| Ok((name, value_from_json(value, options)?)));
+                Value::Map(fields.collect::<Result<_, Error>>()?)
+            }
+        },
+    };
+    Ok(value)
+}
+
+impl JsonOptions {
+    fn tag(&self, tag: &str, json: Json) -> Json {
+        if self.tagged {
+            json!({ tag: json })
+        } else {
+            json
+        }
+    }
+
+    /// Splits a tagged value into its tag and its value, or returns the object unchanged.
+    fn untag(&self, fields: Map<String, Json>) -> Result<(String, Json), Map<String, Json>> {
+        let tags = [
+            TIMESTAMP_TAG,
+            BYTES_TAG,
+            GEO_POINT_TAG,
+            KEY_TAG,
+            REFERENCE_TAG,
+        ];
+        let tagged = self.tagged
+            && fields.len() == 1
+            && fields.keys().all(|name| tags.contains(&name.as_str()));
+        if tagged {
+            Ok(fields.into_iter().next().unwrap())
+        } else {
+            Err(fields)
+        }
+    }
+}
+
+fn from_tagged(tag: String, json: Json, options: &JsonOptions) -> Result<Value, Error> {
+    let value = match tag.as_str() {
+        TIMESTAMP_TAG => {
+            let timestamp = match options.timestamps {
+                TimestampFormat::Rfc3339 => timestamp_from_rfc3339(serde_json::from_value(json)?)?,
+                TimestampFormat::Object => {
+                    let TimestampObject { seconds, nanos } = serde_json::from_value(json)?;
+                    prost_types::Timestamp { seconds, nanos }
+                }
+            };
+            Value::Timestamp(from_proto_timestamp(timestamp))
+        }
+        BYTES_TAG => {
+            let bytes = match options.bytes {
+                BytesFormat::Base64 => {
+                    let bytes: String = serde_json::from_value(json)?;
+                    base64::decode(&bytes).map_err(serde_json::Error::custom)?
+                }
+                BytesFormat::Array => serde_json::from_value(json)?,
+            };
+            Value::Bytes(bytes)
+        }
+        GEO_POINT_TAG => {
+            let (latitude, longitude) = match options.geo_points {
+                GeoPointFormat::Object => {
+                    let GeoPointObject {
+                        latitude,
+                        longitude,
+                    } = serde_json::from_value(json)?;
+                    (latitude, longitude)
+                }
+                GeoPointFormat::Array => serde_json::from_value(json)?,
+            };
+            Value::GeoPoint(GeoPoint {
+                latitude,
+                longitude,
+            })
+        }
+        KEY_TAG => Value::Key(datastore::v1::Key(key_from_json(json, options.keys)?)),
+        _ => Value::Reference(serde_json::from_value(json)?),
+    };
+    Ok(value)
+}
+
+fn timestamp_to_rfc3339(timestamp: &prost_types::Timestamp) -> Option<String> {
+    let time = NaiveDateTime::from_timestamp_opt(timestamp.seconds, timestamp.nanos as u32)?;
+    let time = DateTime::<Utc>::from_utc(time, Utc);
+    Some(time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+fn timestamp_from_rfc3339(time: String) -> Result<prost_types::Timestamp, serde_json::Error> {
+    let time = DateTime::parse_from_rfc3339(&time).map_err(serde_json::Error::custom)?;
+    Ok(prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    })
+}
+
+fn key_to_json(key: Key, format: KeyFormat) -> Json {
+    match format {
+        KeyFormat::Path => {
+            let path = key.path.into_iter().flat_map(|element| {
+                let id = match element.id_type {
+                    Some(IdType::Id(id)) => Json::from(id),
+                    Some(IdType::Name(name)) => Json::String(name),
+                    None => Json::Null,
+                };
+                vec![Json::String(element.kind), id]
+            });
+            Json::Array(path.collect())
+        }
+        KeyFormat::Object => {
+            let (project_id, namespace_id) = match key.partition_id {
+                Some(partition_id) => (
+                    Some(partition_id.project_id),
+                    Some(partition_id.namespace_id),
+                ),
+                None => (None, None),
+            };
+            let path = key.path.into_iter().map(|element| {
+                let (id, name) = match element.id_type {
+                    Some(IdType::Id(id)) => (Some(id), None),
+                    Some(IdType::Name(name)) => (None, Some(name)),
+                    None => (None, None),
+                };
+                PathObject {
+                    kind: element.kind,
+                    id,
+                    name,
+                }
+            });
+            json!(KeyObject {
+                project_id,
+                namespace_id,
+                path: path.collect(),
+            })
+        }
+    }
+}
+
+fn key_from_json(json: Json, format: KeyFormat) -> Result<Key, serde_json::Error> {
+    match format {
+        KeyFormat::Path => {
+            let path: Vec<Json> = serde_json::from_value(json)?;
+            if path.len() & 1 == 1 {
+                return Err(serde_json::Error::custom(
+                    "a key path must have an ID or name after each kind",
+                ));
+            }
+            let path = path.chunks(2).map(|element| {
+                let id_type = match &element[1] {
+                    Json::Null => None,
+                    Json::String(name) => Some(IdType::Name(name.clone())),
+                    id => Some(IdType::Id(serde_json::from_value(id.clone())?)),
+                };
+                Ok(PathElement {
+                    kind: serde_json::from_value(element[0].clone())?,
+                    id_type,
+                })
+            });
+            Ok(Key {
+                partition_id: None,
+                path: path.collect::<Result<_, serde_json::Error>>()?,
+            })
+        }
+        KeyFormat::Object => {
+            let key: KeyObject = serde_json::from_value(json)?;
+            let partition_id = match (key.project_id, key.namespace_id) {
+                (None, None) => None,
+                (project_id, namespace_id) => Some(PartitionId {
+                    project_id: project_id.unwrap_or_default(),
+                    namespace_id: namespace_id.unwrap_or_default(),
+                }),
+            };
+            let path = key.path.into_iter().map(|element| PathElement {
+                kind: element.kind,
+                id_type: match (element.id, element.name) {
+                    (Some(id), _) => Some(IdType::Id(id)),
+                    (None, Some(name)) => Some(IdType::Name(name)),
+                    (None, None) => None,
+                },
+            });
+            Ok(Key {
+                partition_id,
+                path: path.collect(),
+            })
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_json, to_json, BytesFormat, GeoPointFormat, JsonOptions, KeyFormat, TimestampFormat,
+    };
+    use crate::{
+        proto::google::{datastore, firestore},
+        Error, Value,
+    };
+    use serde_json::json;
+
+    fn entity() -> serde_json::Value {
+        json!({
+            "null": null,
+            "bool": true,
+            "integer": -1,
+            "double": 0.5,
+            "string": "a",
+            "array": [1, [], {"a": 2}],
+            "timestamp": {"$timestamp": "2020-12-29T00:00:00.000000100Z"},
+            "bytes": {"$bytes": "AAEC"},
+            "geo": {"$geoPoint": {"latitude": 35.6, "longitude": 139.7}},
+            "key": {"$key": ["Parent", "a", "Child", 1, "Incomplete", null]},
+        })
+    }
+
+    fn tagged() -> JsonOptions {
+        JsonOptions {
+            tagged: true,
+            ..JsonOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_tagged() {
+        let options = tagged();
+        let value: datastore::v1::Value = from_json(entity(), &options).unwrap();
+        assert_eq!(entity(), to_json(value, &options));
+
+        let mut document = entity();
+        document["key"] = json!({"$reference": "projects/p/databases/(default)/documents/c/d"});
+        let value: firestore::v1::Value = from_json(document.clone(), &options).unwrap();
+        assert_eq!(document, to_json(value, &options));
+
+        match from_json::<firestore::v1::Value>(entity(), &options) {
+            Err(Error::UnsupportedValue(name)) => assert_eq!("Key", name),
+            result => panic!("{:?}", result),
+        }
+        match from_json::<datastore::v1::Value>(document, &options) {
+            Err(Error::UnsupportedValue(name)) => assert_eq!("Reference", name),
+            result => panic!("{:?}", result),
+        }
+        let invalid = json!({"time": {"$timestamp": "yesterday"}});
+        assert!(from_json::<firestore::v1::Value>(invalid, &options).is_err());
+    }
+
+    #[test]
+    fn test_formats() {
+        let value: datastore::v1::Value = from_json(entity(), &tagged()).unwrap();
+        let options = JsonOptions {
+            bytes: BytesFormat::Array,
+            timestamps: TimestampFormat::Object,
+            geo_points: GeoPointFormat::Array,
+            keys: KeyFormat::Object,
+            tagged: false,
+        };
+        let json = to_json(value.clone(), &options);
+        assert_eq!(json!([0, 1, 2]), json["bytes"]);
+        assert_eq!(
+            json!({"seconds": 1609200000, "nanos": 100}),
+            json["timestamp"]
+        );
+        assert_eq!(json!([35.6, 139.7]), json["geo"]);
+        assert_eq!(
+            json!({"path": [
+                {"kind": "Parent", "name": "a"},
+                {"kind": "Child", "id": 1},
+                {"kind": "Incomplete"},
+            ]}),
+            json["key"]
+        );
+
+        let options = JsonOptions {
+            tagged: true,
+            ..options
+        };
+        let json = to_json(value.clone(), &options);
+        assert_eq!(value, from_json(json, &options).unwrap());
+    }
+
+    #[test]
+    fn test_untagged() {
+        let value: datastore::v1::Value = from_json(entity(), &tagged()).unwrap();
+        let json = to_json(value, &JsonOptions::default());
+        assert_eq!(json!("2020-12-29T00:00:00.000000100Z"), json["timestamp"]);
+        assert_eq!(json!("AAEC"), json["bytes"]);
+        assert_eq!(
+            json!(["Parent", "a", "Child", 1, "Incomplete", null]),
+            json["key"]
+        );
+
+        let value: datastore::v1::Value = from_json(json.clone(), &tagged()).unwrap();
+        assert_eq!(json, to_json(value, &tagged()));
+    }
+
+    #[test]
+    fn test_backends() {
+        let document = json!({"a": {"$reference": "projects/p/databases/(default)/documents/c/d"}});
+        let value = crate::firestore::v1::from_json(document.clone(), &tagged()).unwrap();
+        assert_eq!(
+            Value::Reference("projects/p/databases/(default)/documents/c/d".into()),
+            value["a"]
+        );
+        assert_eq!(
+            document,
+            crate::firestore::v1::to_json(&value, &tagged()).unwrap()
+        );
+        assert!(crate::datastore::v1::to_json(&value, &tagged()).is_err());
+        assert!(crate::datastore::v1::from_json(document, &tagged()).is_err());
+    }
+}
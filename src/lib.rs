@@ -1,11 +1,34 @@
 #[macro_use]
 mod service;
+mod client;
 mod config;
+mod error;
+pub mod error_details;
+#[cfg(feature = "json")]
+pub mod json;
+mod options;
 mod proto;
+mod recorder;
+mod retry;
 mod serde_properties;
+mod trace;
 mod util;
+mod value;
 
-pub use service::google::{datastore, firestore, pubsub};
+pub use client::{Client, ClientConfig};
+pub use error::{Error, ErrorKind};
+pub use error_details::ErrorDetails;
+pub use options::CallOptions;
+#[cfg(feature = "metrics")]
+pub use recorder::MetricsCrateRecorder;
+pub use recorder::{set_metrics_recorder, CallMetrics, MetricsRecorder};
+pub use retry::RetryPolicy;
+pub use service::{
+    auth,
+    google::{datastore, firestore, pubsub},
+    ChannelSelection, ChannelSettings, EndpointSettings, Service,
+};
+pub use value::{GeoPoint, Value};
 
 pub fn init(project_id: impl Into<String>) {
     config::init(project_id);
@@ -0,0 +1,148 @@
+use crate::{
+    retry::{CallError, RetryPolicy},
+    service::TIMEOUT_HEADER,
+    Client,
+};
+use std::time::Duration;
+use tonic::{
+    metadata::{MetadataKey, MetadataValue},
+    Request, Status,
+};
+
+/// The header that tells the backend which resource a call is about.
+pub(crate) const ROUTING_HEADER: &str = "x-goog-request-params";
+
+/// Options for a single call, such as the one passed to `Datastore::get_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    deadline: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    metadata: Vec<(String, String)>,
+}
+
+impl CallOptions {
+    pub fn new() -> CallOptions {
+        CallOptions::default()
+    }
+
+    /// Fails the call with a timeout error unless it completes within `timeout`, including the
+    /// time spent on retries. The remaining time is also sent to the server as the gRPC deadline.
+    pub fn deadline(mut self, timeout: Duration) -> CallOptions {
+        self.deadline = Some(timeout);
+        self
+    }
+
+    /// Retries the call with `policy` instead of the policy of the client.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> CallOptions {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Sends an additional metadata header with the call.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> CallOptions {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sends `key=value` in the routing header, such as `name=projects/p/topics/t`.
+    pub(crate) fn resource(self, key: &str, value: &str) -> CallOptions {
+        let params = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair(key, value)
+            .finish();
+        self.metadata(ROUTING_HEADER, params)
+    }
+
+    pub(crate) fn deadline_duration(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// The retry policy of the call, falling back to the one of `client`.
+    pub(crate) fn policy(&self, client: Option<&Client>) -> RetryPolicy {
+        match (&self.retry_policy, client) {
+            (Some(policy), _) => policy.clone(),
+            (None, Some(client)) => client.retry_policy().clone(),
+            (None, None) => RetryPolicy::default(),
+        }
+    }
+
+    /// Adds the metadata and the remaining time of the call to `request`.
+    pub(crate) fn apply<T>(
+        &self,
+        request: &mut Request<T>,
+        remaining: Option<Duration>,
+    ) -> Result<(), CallError> {
+        let invalid = |message| CallError::Status(Box::new(Status::invalid_argument(message)));
+        let metadata = request.metadata_mut();
+        for (key, value) in &self.metadata {
+            let key = MetadataKey::from_bytes(key.as_bytes())
+                .map_err(|_| invalid(format!("Invalid metadata key {:?}", key)))?;
+            let value = MetadataValue::from_str(value)
+                .map_err(|_| invalid(format!("Invalid metadata value for {:?}", key)))?;
+            metadata.append(key, value);
+        }
+        if let Some(remaining) = remaining {
+            let timeout = MetadataValue::from_str(&grpc_timeout(remaining)).unwrap();
+            metadata.insert(TIMEOUT_HEADER, timeout);
+        }
+        Ok(())
+    }
+}
+
+/// Encodes `timeout` in the format of the `grpc-timeout` header, which allows at most 8 digits.
+fn grpc_timeout(timeout: Duration) -> String {
+    const MAX: u128 = 99_999_999;
+    let millis = timeout.as_millis();
+    if millis <= MAX {
+        format!("{}m", millis)
+    } else if u128::from(timeout.as_secs()) <= MAX {
+        format!("{}S", timeout.as_secs())
+    } else {
+        format!(
+            "{}H",
+            std::cmp::min(u128::from(timeout.as_secs() / 3600), MAX)
+        )
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{grpc_timeout, CallOptions, ROUTING_HEADER};
+    use crate::service::TIMEOUT_HEADER;
+    use std::time::Duration;
+    use tonic::Request;
+
+    #[test]
+    fn test_grpc_timeout() {
+        assert_eq!("1500m", grpc_timeout(Duration::from_millis(1500)));
+        assert_eq!("100000S", grpc_timeout(Duration::from_secs(100_000)));
+        assert_eq!(
+            "27777777H",
+            grpc_timeout(Duration::from_secs(99_999_999_999))
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let options = CallOptions::new().metadata("x-goog-request-params", "a=b");
+        let mut request = Request::new(());
+        options
+            .apply(&mut request, Some(Duration::from_secs(2)))
+            .unwrap();
+        let metadata = request.metadata();
+        assert_eq!("a=b", metadata.get("x-goog-request-params").unwrap());
+        assert_eq!("2000m", metadata.get(TIMEOUT_HEADER).unwrap());
+
+        let options = CallOptions::new().resource("topic", "projects/p/topics/t");
+        let mut request = Request::new(());
+        options.apply(&mut request, None).unwrap();
+        assert_eq!(
+            "topic=projects%2Fp%2Ftopics%2Ft",
+            request.metadata().get(ROUTING_HEADER).unwrap()
+        );
+
+        let options = CallOptions::new().metadata("invalid key", "a");
+        assert!(options.apply(&mut Request::new(()), None).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 pub(crate) mod datastore;
 pub(crate) mod firestore;
 pub(crate) mod pubsub;
+// Not every error detail type is decoded by the clients.
+#[allow(dead_code)]
 pub(crate) mod rpc {
     tonic::include_proto!("google.rpc");
 }
@@ -0,0 +1,195 @@
+use once_cell::sync::Lazy;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+use tonic::Code;
+
+static RECORDER: Lazy<RwLock<Option<Arc<dyn MetricsRecorder>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Records the metrics of the global API, and of every [`Client`](crate::Client) without a
+/// recorder of its own.
+pub fn set_metrics_recorder(recorder: Arc<dyn MetricsRecorder>) {
+    *RECORDER.write().unwrap() = Some(recorder);
+}
+
+/// Receives the metrics of the calls made by the crate.
+///
+/// Every method does nothing by default, so a recorder only implements the ones it needs.
+pub trait MetricsRecorder: Send + Sync {
+    /// Called once for every attempt of a call, when its response has ended.
+    fn record_call(&self, call: &CallMetrics) {
+        let _ = call;
+    }
+
+    /// Called whenever the number of outstanding Pub/Sub messages of a topic or subscription
+    /// changes.
+    ///
+    /// A published message is outstanding until the server has responded to it, and a received
+    /// message until it is acknowledged, nacked or dropped.
+    fn record_outstanding_messages(&self, resource: &str, count: usize) {
+        let _ = (resource, count);
+    }
+}
+
+/// The metrics of one attempt of a call.
+#[derive(Debug, Clone)]
+pub struct CallMetrics<'a> {
+    /// The gRPC service, such as `google.pubsub.v1.Publisher`.
+    pub service: &'a str,
+    pub method: &'a str,
+    /// The number of the attempt, which is greater than 1 for retries.
+    pub attempt: u32,
+    pub code: Code,
+    /// The time from sending the request until the end of the response.
+    pub latency: Duration,
+    /// The size of the request body, which holds the encoded messages with their gRPC framing.
+    pub bytes_sent: u64,
+    /// The size of the response body.
+    pub bytes_received: u64,
+}
+
+/// The recorder of a client, or else the global one.
+pub(crate) fn current(
+    recorder: Option<&Arc<dyn MetricsRecorder>>,
+) -> Option<Arc<dyn MetricsRecorder>> {
+    recorder
+        .cloned()
+        .or_else(|| RECORDER.read().unwrap().clone())
+}
+
+/// Counts the outstanding messages of a topic or subscription.
+pub(crate) struct Outstanding {
+    resource: String,
+    recorder: Option<Arc<dyn MetricsRecorder>>,
+    count: AtomicUsize,
+}
+
+impl Outstanding {
+    pub(crate) fn new(
+        resource: impl Into<String>,
+        recorder: Option<&Arc<dyn MetricsRecorder>>,
+    ) -> Arc<Outstanding> {
+        Arc::new(Outstanding {
+            resource: resource.into(),
+            recorder: recorder.cloned(),
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    /// Counts a message as outstanding until the returned value is released or dropped.
+    pub(crate) fn track(self: &Arc<Self>) -> OutstandingMessage {
+        let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        self.report(count);
+        OutstandingMessage {
+            outstanding: self.clone(),
+            released: AtomicBool::new(false),
+        }
+    }
+
+    fn report(&self, count: usize) {
+        if let Some(recorder) = current(self.recorder.as_ref()) {
+            recorder.record_outstanding_messages(&self.resource, count);
+        }
+    }
+}
+
+/// A message counted by [`Outstanding`].
+pub(crate) struct OutstandingMessage {
+    outstanding: Arc<Outstanding>,
+    released: AtomicBool,
+}
+
+impl OutstandingMessage {
+    pub(crate) fn release(&self) {
+        if !self.released.swap(true, Ordering::SeqCst) {
+            let count = self.outstanding.count.fetch_sub(1, Ordering::SeqCst) - 1;
+            self.outstanding.report(count);
+        }
+    }
+}
+
+impl Drop for OutstandingMessage {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl std::fmt::Debug for OutstandingMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("OutstandingMessage")
+            .field("resource", &self.outstanding.resource)
+            .finish()
+    }
+}
+
+/// Records to the global recorder of the `metrics` crate.
+///
+/// Every attempt increments the `grpc_gcp_calls_total` counter, and retries also increment
+/// `grpc_gcp_retries_total`. The latency goes to the `grpc_gcp_call_duration_seconds` histogram,
+/// the sizes to the `grpc_gcp_sent_bytes_total` and `grpc_gcp_received_bytes_total` counters, and
+/// outstanding Pub/Sub messages to the `grpc_gcp_outstanding_messages` gauge.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsCrateRecorder;
+
+#[cfg(feature = "metrics")]
+impl MetricsRecorder for MetricsCrateRecorder {
+    fn record_call(&self, call: &CallMetrics) {
+        let labels = [
+            ("service", call.service.to_string()),
+            ("method", call.method.to_string()),
+            ("code", format!("{:?}", call.code)),
+        ];
+        metrics::counter!("grpc_gcp_calls_total", 1, &labels);
+        if call.attempt > 1 {
+            metrics::counter!("grpc_gcp_retries_total", 1, &labels);
+        }
+        metrics::histogram!("grpc_gcp_call_duration_seconds", call.latency, &labels);
+        metrics::counter!("grpc_gcp_sent_bytes_total", call.bytes_sent, &labels);
+        metrics::counter!(
+            "grpc_gcp_received_bytes_total",
+            call.bytes_received,
+            &labels
+        );
+    }
+
+    fn record_outstanding_messages(&self, resource: &str, count: usize) {
+        let labels = [("resource", resource.to_string())];
+        metrics::gauge!("grpc_gcp_outstanding_messages", count as f64, &labels);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{MetricsRecorder, Outstanding};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Counts(Mutex<Vec<usize>>);
+
+    impl MetricsRecorder for Counts {
+        fn record_outstanding_messages(&self, _: &str, count: usize) {
+            self.0.lock().unwrap().push(count);
+        }
+    }
+
+    #[test]
+    fn test_outstanding() {
+        let counts = Arc::new(Counts::default());
+        let recorder: Arc<dyn MetricsRecorder> = counts.clone();
+        let outstanding = Outstanding::new("projects/p/topics/t", Some(&recorder));
+        let first = outstanding.track();
+        let second = outstanding.track();
+        first.release();
+        first.release();
+        drop(first);
+        drop(second);
+        assert_eq!(vec![1, 2, 1, 0], *counts.0.lock().unwrap());
+    }
+}
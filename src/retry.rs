@@ -0,0 +1,361 @@
+use crate::{
+    trace::{self, Trace},
+    CallOptions, Client, ErrorDetails,
+};
+use rand::Rng;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, timeout};
+use tonic::{Code, Request, Response, Status};
+
+/// How failed RPCs are retried.
+///
+/// Only idempotent RPCs, such as lookups, queries and deletions, are retried. Other RPCs are sent
+/// once, because a failure does not tell whether the server has already applied them.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The status codes that are retried.
+    pub retryable_codes: Vec<Code>,
+    /// The wait before the second attempt.
+    pub initial_backoff: Duration,
+    /// The upper bound of the wait between attempts.
+    pub max_backoff: Duration,
+    /// The factor by which the wait grows after each attempt.
+    pub multiplier: f64,
+    /// The fraction of each wait that is randomized, from `0.0` for none to `1.0` for all of it.
+    pub jitter: f64,
+    /// The total time after which no further attempt is started, or `None` to retry indefinitely.
+    pub deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            retryable_codes: Vec::new(),
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn next_backoff(&self, backoff: Duration) -> Duration {
+        std::cmp::min(backoff.mul_f64(self.multiplier), self.max_backoff)
+    }
+
+    fn jittered(&self, backoff: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        backoff.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
+    }
+
+    fn retries(&self, status: &Status) -> bool {
+        self.retryable_codes.contains(&status.code())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retryable_codes: vec![Code::Unavailable, Code::DeadlineExceeded],
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(60),
+            multiplier: 1.3,
+            jitter: 0.5,
+            deadline: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// The waits between the attempts of a call according to a [`RetryPolicy`].
+pub(crate) struct Backoff {
+    policy: RetryPolicy,
+    started: Instant,
+    backoff: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(policy: RetryPolicy) -> Backoff {
+        Backoff {
+            started: Instant::now(),
+            backoff: policy.initial_backoff,
+            policy,
+        }
+    }
+
+    /// The wait before the next attempt, which is `server_delay` when the server asked for one.
+    ///
+    /// Returns `None` when the next attempt would start after the deadline of the policy or after
+    /// `deadline`, because a wait that outlasts the deadline would leave no time for it.
+    pub(crate) fn next(
+        &mut self,
+        server_delay: Option<Duration>,
+        deadline: Option<Instant>,
+    ) -> Option<Duration> {
+        let wait = server_delay.unwrap_or_else(|| self.policy.jittered(self.backoff));
+        let within_policy = match self.policy.deadline {
+            Some(timeout) => self.started.elapsed() + wait < timeout,
+            None => true,
+        };
+        let within_call = match deadline {
+            Some(deadline) => Instant::now() + wait < deadline,
+            None => true,
+        };
+        if !(within_policy && within_call) {
+            return None;
+        }
+        self.backoff = self.policy.next_backoff(self.backoff);
+        Some(wait)
+    }
+}
+
+/// Why a call failed.
+#[derive(Debug)]
+pub(crate) enum CallError {
+    Status(Box<Status>),
+    /// The deadline of the call has passed.
+    Timeout,
+}
+
+/// Sends an idempotent RPC with clones of `client` and `request` until it succeeds, the retry
+/// policy gives up or the deadline in `options` passes.
+///
+/// The retry policy is the one in `options`, or else the one of `owner`, the [`Client`] the call
+/// is made through. A retry delay sent by the server replaces the backoff of the policy.
+pub(crate) async fn call<C, R, T, F, Fut>(
+    options: &CallOptions,
+    owner: Option<&Client>,
+    client: &C,
+    request: R,
+    rpc: F,
+) -> Result<Response<T>, CallError>
+where
+    C: Clone,
+    R: Clone,
+    F: Fn(C, Request<R>) -> Fut,
+    Fut: Future<Output = Result<Response<T>, Status>>,
+{
+    let policy = options.policy(owner);
+    let deadline = options
+        .deadline_duration()
+        .map(|timeout| Instant::now() + timeout);
+    let mut backoff = Backoff::new(policy);
+    let trace = Trace::new();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let remaining = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::from_secs(0) => Some(remaining),
+                _ => return Err(CallError::Timeout),
+            },
+            None => None,
+        };
+        let mut request = Request::new(request.clone());
+        options.apply(&mut request, remaining)?;
+        let sent = trace.attempt(attempt, request, |request| rpc(client.clone(), request));
+        let result = match remaining {
+            Some(remaining) => match timeout(remaining, sent).await {
+                Ok(result) => result,
+                Err(_) => return Err(CallError::Timeout),
+            },
+            None => sent.await,
+        };
+        let status = match result {
+            Ok(response) => return Ok(response),
+            Err(status) => status,
+        };
+        let expired = matches!(deadline, Some(deadline) if deadline <= Instant::now());
+        if status.code() == Code::DeadlineExceeded && expired {
+            return Err(CallError::Timeout);
+        }
+        let wait = if backoff.policy.retries(&status) {
+            backoff.next(server_delay(&status), deadline)
+        } else {
+            None
+        };
+        match wait {
+            Some(wait) => delay_for(wait).await,
+            None => return Err(CallError::Status(Box::new(status))),
+        }
+    }
+}
+
+/// Sends an RPC that is not idempotent as a single attempt, within the deadline in `options`.
+pub(crate) async fn send<R, T, F, Fut>(
+    options: &CallOptions,
+    request: R,
+    rpc: F,
+) -> Result<Response<T>, CallError>
+where
+    F: FnOnce(Request<R>) -> Fut,
+    Fut: Future<Output = Result<Response<T>, Status>>,
+{
+    let remaining = options.deadline_duration();
+    let mut request = Request::new(request);
+    options.apply(&mut request, remaining)?;
+    let sent = trace::send(request, rpc);
+    let result = match remaining {
+        Some(remaining) => timeout(remaining, sent)
+            .await
+            .map_err(|_| CallError::Timeout)?,
+        None => sent.await,
+    };
+    result.map_err(|status| CallError::Status(Box::new(status)))
+}
+
+/// The delay before the next attempt that the server asked for in a `RetryInfo` detail.
+fn server_delay(status: &Status) -> Option<Duration> {
+    ErrorDetails::from_status(status).retry_info?.retry_delay
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{call, server_delay, Backoff, CallError, RetryPolicy};
+    use crate::{proto::google::rpc, CallOptions};
+    use prost::Message;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+    use tokio::time::delay_for;
+    use tonic::{Code, Response, Status};
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(3),
+            multiplier: 2.0,
+            jitter: 0.5,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(
+            Duration::from_secs(2),
+            policy.next_backoff(Duration::from_secs(1))
+        );
+        assert_eq!(
+            Duration::from_secs(3),
+            policy.next_backoff(Duration::from_secs(2))
+        );
+        for _ in 0..100 {
+            let wait = policy.jittered(Duration::from_secs(2));
+            assert!(wait > Duration::from_secs(1) && wait <= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn test_retries() {
+        let policy = RetryPolicy::default();
+        let unavailable = Status::new(Code::Unavailable, "");
+        assert!(policy.retries(&unavailable));
+        assert!(!policy.retries(&Status::new(Code::NotFound, "")));
+        assert!(!RetryPolicy::none().retries(&unavailable));
+
+        let mut backoff = Backoff::new(policy);
+        assert!(backoff.next(None, None).is_some());
+        assert!(backoff.next(Some(Duration::from_secs(61)), None).is_none());
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert!(backoff
+            .next(Some(Duration::from_secs(2)), Some(deadline))
+            .is_none());
+    }
+
+    fn exhausted(delay: prost_types::Duration) -> Status {
+        let info = rpc::RetryInfo {
+            retry_delay: Some(delay),
+        };
+        let mut value = Vec::new();
+        info.encode(&mut value).unwrap();
+        let details = rpc::Status {
+            code: Code::ResourceExhausted as i32,
+            message: String::new(),
+            details: vec![prost_types::Any {
+                type_url: "type.googleapis.com/google.rpc.RetryInfo".into(),
+                value,
+            }],
+        };
+        let mut bytes = Vec::new();
+        details.encode(&mut bytes).unwrap();
+        Status::with_details(Code::ResourceExhausted, "", bytes.into())
+    }
+
+    #[test]
+    fn test_server_delay() {
+        let status = exhausted(prost_types::Duration {
+            seconds: 0,
+            nanos: 250_000_000,
+        });
+        assert_eq!(Some(Duration::from_millis(250)), server_delay(&status));
+        assert_eq!(None, server_delay(&Status::new(Code::Unavailable, "")));
+    }
+
+    #[tokio::test]
+    async fn test_call() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let options = CallOptions::new().retry_policy(policy);
+        let response = call(&options, None, &attempts, (), |attempts, _| async move {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(Status::new(Code::Unavailable, "")),
+                _ => Ok(Response::new("done")),
+            }
+        })
+        .await;
+        assert_eq!("done", response.unwrap().into_inner());
+        assert_eq!(3, attempts.load(Ordering::SeqCst));
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let response = call(&options, None, &attempts, (), |attempts, _| async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<Response<()>, _>(Status::new(Code::InvalidArgument, ""))
+        })
+        .await;
+        match response.unwrap_err() {
+            CallError::Status(status) => assert_eq!(Code::InvalidArgument, status.code()),
+            CallError::Timeout => panic!("unexpected timeout"),
+        }
+        assert_eq!(1, attempts.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        let options = CallOptions::new().deadline(Duration::from_millis(50));
+        let response = call(&options, None, &(), (), |_, _| async {
+            delay_for(Duration::from_secs(10)).await;
+            Ok(Response::new(()))
+        })
+        .await;
+        assert!(matches!(response, Err(CallError::Timeout)));
+
+        // A retry delay beyond the deadline fails the call instead of waiting past it.
+        let policy = RetryPolicy {
+            retryable_codes: vec![Code::ResourceExhausted],
+            ..RetryPolicy::default()
+        };
+        let options = CallOptions::new()
+            .deadline(Duration::from_secs(1))
+            .retry_policy(policy);
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let response = call(&options, None, &attempts, (), |attempts, _| async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<Response<()>, _>(exhausted(prost_types::Duration {
+                seconds: 10,
+                nanos: 0,
+            }))
+        })
+        .await;
+        match response.unwrap_err() {
+            CallError::Status(status) => assert_eq!(Code::ResourceExhausted, status.code()),
+            CallError::Timeout => panic!("unexpected timeout"),
+        }
+        assert_eq!(1, attempts.load(Ordering::SeqCst));
+    }
+}
@@ -1,11 +1,15 @@
 pub(crate) use super::{
     error::{Error, Result},
-    ArrayValueTrait, KeyValueSet, TraceKey, ValueTrait, ValueTypeRef,
+    ArrayValueTrait, KeyValueSet, LatLngTrait, TraceKey, ValueTrait, ValueTypeRef,
 };
+use crate::value;
 use core::panic;
 use de::SeqAccess;
 use serde::{
-    de::{self, DeserializeSeed, EnumAccess, MapAccess, VariantAccess, Visitor},
+    de::{
+        self, value::SeqDeserializer, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+        VariantAccess, Visitor,
+    },
     Deserialize,
 };
 use std::{collections::HashMap, convert::TryFrom, iter::Peekable, mem};
@@ -255,14 +259,86 @@ impl<Value: ValueTrait> Deserializer<Value> {
     }
 }
 
+impl<Value: ValueTrait> Deserializer<Value> {
+    /// Deserializes a [`crate::Value`], handing it the types that serde has no counterpart of as
+    /// maps with a single private key.
+    fn deserialize_value<'de, V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let typed = match self.peek()? {
+            PeekedBundleElement::Value(value) => matches!(
+                value.get_value_type().unwrap(),
+                ValueTypeRef::TimestampValue(_)
+                    | ValueTypeRef::GeoPointValue(_)
+                    | ValueTypeRef::ReferenceValue(_)
+                    | ValueTypeRef::KeyValue(_)
+            ),
+            _ => false,
+        };
+        if !typed {
+            return de::Deserializer::deserialize_any(self, visitor);
+        }
+        let KeyValueSet(_, value) = self.pop()?.key_value_set();
+        match value.get_value_type().unwrap() {
+            ValueTypeRef::TimestampValue(timestamp) => {
+                let timestamp = vec![timestamp.seconds, timestamp.nanos.into()];
+                visitor.visit_map(TypedValue::new(
+                    value::TIMESTAMP_TOKEN,
+                    SeqDeserializer::new(timestamp.into_iter()),
+                ))
+            }
+            ValueTypeRef::GeoPointValue(geo_point) => {
+                let geo_point = vec![geo_point.get_latitude(), geo_point.get_longitude()];
+                visitor.visit_map(TypedValue::new(
+                    value::GEO_POINT_TOKEN,
+                    SeqDeserializer::new(geo_point.into_iter()),
+                ))
+            }
+            ValueTypeRef::ReferenceValue(reference) => visitor.visit_map(TypedValue::new(
+                value::REFERENCE_TOKEN,
+                reference.clone().into_deserializer(),
+            )),
+            ValueTypeRef::KeyValue(key) => {
+                let mut bytes = Vec::new();
+                prost::Message::encode(key, &mut bytes).unwrap();
+                visitor.visit_map(TypedValue::new(
+                    value::KEY_TOKEN,
+                    SeqDeserializer::new(bytes.into_iter()),
+                ))
+            }
+            _ => common_panic!(),
+        }
+    }
+}
+
 impl<'de, 'a, Value: ValueTrait> de::Deserializer<'de> for &'a mut Deserializer<Value> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        let value = match self.peek()? {
+            PeekedBundleElement::Key(_) => return self.deserialize_string(visitor),
+            PeekedBundleElement::Value(value) => value,
+            PeekedBundleElement::EndOfBundle => common_panic!(),
+        };
+        match value.get_value_type().unwrap() {
+            ValueTypeRef::NullValue(_) => self.deserialize_unit(visitor),
+            ValueTypeRef::BooleanValue(_) => self.deserialize_bool(visitor),
+            ValueTypeRef::IntegerValue(_) => self.deserialize_i64(visitor),
+            ValueTypeRef::DoubleValue(_) => self.deserialize_f64(visitor),
+            ValueTypeRef::StringValue(_) | ValueTypeRef::ReferenceValue(_) => {
+                self.deserialize_string(visitor)
+            }
+            ValueTypeRef::BytesValue(_) => self.deserialize_byte_buf(visitor),
+            ValueTypeRef::ArrayValue(_) => self.deserialize_seq(visitor),
+            ValueTypeRef::TimestampValue(_)
+            | ValueTypeRef::GeoPointValue(_)
+            | ValueTypeRef::MapValue(_)
+            | ValueTypeRef::KeyValue(_) => self.deserialize_map(visitor),
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -413,11 +489,15 @@ impl<'de, 'a, Value: ValueTrait> de::Deserializer<'de> for &'a mut Deserializer<
         self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        if name == value::VALUE_TOKEN {
+            self.deserialize_value(visitor)
+        } else {
+            visitor.visit_newtype_struct(self)
+        }
     }
 
     fn deserialize_seq<V>(mut self, visitor: V) -> Result<V::Value>
@@ -590,6 +670,42 @@ impl<'a, 'de, Value: ValueTrait> MapAccess<'de> for Entries<'a, Value> {
     }
 }
 
+/// A map of the private key of a type, and the value of the type.
+struct TypedValue<D> {
+    token: Option<&'static str>,
+    value: Option<D>,
+}
+
+impl<D> TypedValue<D> {
+    fn new(token: &'static str, value: D) -> Self {
+        TypedValue {
+            token: Some(token),
+            value: Some(value),
+        }
+    }
+}
+
+impl<'de, D: de::Deserializer<'de, Error = Error>> MapAccess<'de> for TypedValue<D> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.token.take() {
+            Some(token) => Ok(Some(seed.deserialize(token.into_deserializer())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.value.take().unwrap())
+    }
+}
+
 struct Enum<'a, Value: ValueTrait> {
     de: &'a mut Deserializer<Value>,
 }
@@ -57,6 +57,7 @@ pub(crate) trait ArrayValueTrait<Value: ValueTrait> {
 }
 
 pub(crate) trait LatLngTrait {
+    fn new(latitude: f64, longitude: f64) -> Self;
     fn get_latitude(&self) -> f64;
     fn get_longitude(&self) -> f64;
 
@@ -167,7 +168,13 @@ pub(crate) trait ValueTrait: Sized + Display + 'static {
     type MapValue: MapValueTrait<Self> + Debug;
 
     fn from(input: HashMap<String, Self>) -> Self;
-    fn new(value_type: ValueType<Self>) -> Self;
+    /// Returns `None` for the types that the database cannot hold, which are references in
+    /// Datastore and keys in Firestore.
+    fn try_new(value_type: ValueType<Self>) -> Option<Self>;
+
+    fn new(value_type: ValueType<Self>) -> Self {
+        Self::try_new(value_type).unwrap_or_else(|| common_panic!())
+    }
 
     fn get_value_type<'s>(&'s self) -> Option<ValueTypeRef<Self>>;
     fn into_value_type(self) -> Option<ValueType<Self>>;
@@ -198,7 +205,8 @@ pub(crate) trait ValueTrait: Sized + Display + 'static {
         match self.get_value_type().unwrap() {
             ValueTypeRef::MapValue(_)
             | ValueTypeRef::GeoPointValue(_)
-            | ValueTypeRef::TimestampValue(_) => true,
+            | ValueTypeRef::TimestampValue(_)
+            | ValueTypeRef::KeyValue(_) => true,
             _ => false,
         }
     }
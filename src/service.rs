@@ -1,25 +1,118 @@
-use gcp_auth::GCPAuthError;
+use crate::{recorder::MetricsRecorder, Error};
+use std::sync::{Arc, RwLock};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    transport::Channel,
+    Status,
+};
 
 #[macro_use]
 mod macros;
 
 pub mod auth;
+mod endpoint;
 pub mod google;
+mod metered;
+mod pool;
 
+pub use endpoint::EndpointSettings;
+pub use pool::{ChannelSelection, ChannelSettings};
+pub(crate) use pool::{Connector, Transport};
+
+/// A service that a [`Client`](crate::Client) can be configured for separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Service {
+    Datastore,
+    Firestore,
+    PubSub,
+}
+
+impl Service {
+    /// The domain of the default endpoint of the service.
+    pub(crate) fn domain(self) -> &'static str {
+        match self {
+            Service::Datastore => "datastore.googleapis.com",
+            Service::Firestore => "firestore.googleapis.com",
+            Service::PubSub => "pubsub.googleapis.com",
+        }
+    }
+
+    /// The environment variable that connects the service to an emulator.
+    pub(crate) fn emulator_host_var(self) -> &'static str {
+        match self {
+            Service::Datastore => "DATASTORE_EMULATOR_HOST",
+            Service::Firestore => "FIRESTORE_EMULATOR_HOST",
+            Service::PubSub => "PUBSUB_EMULATOR_HOST",
+        }
+    }
+}
+
+/// Emulators accept any token, and the Firestore emulator treats this one as an administrator.
+const EMULATOR_TOKEN: &str = "Bearer owner";
+
+/// Connects over TLS to `domain`, or to the endpoint configured in `endpoint_settings`, or over
+/// plaintext to `emulator_host` when it is set.
+///
+/// The channels of the pool connect lazily, on their first call, unless they connect through a
+/// proxy. The calls are recorded to `recorder`, or else to the global recorder.
 pub(crate) async fn create_channel(
     domain: &str,
-) -> Result<tonic::transport::Channel, tonic::transport::Error> {
-    let tls = tonic::transport::ClientTlsConfig::new().domain_name(domain);
-    let uri: http::uri::Uri = ["https://", domain].concat().parse().unwrap();
-    let channel = tonic::transport::Channel::builder(uri)
-        .tls_config(tls)?
-        .connect()
-        .await?;
-    Ok(channel)
+    emulator_host: Option<String>,
+    endpoint_settings: &EndpointSettings,
+    settings: &ChannelSettings,
+    recorder: Option<Arc<dyn MetricsRecorder>>,
+) -> Result<Transport, Error> {
+    let (endpoint, proxy) = match emulator_host {
+        Some(host) => {
+            let uri: http::Uri = ["http://", &host]
+                .concat()
+                .parse()
+                .map_err(|e| Error::InvalidEndpoint(format!("{:?}: {}", host, e)))?;
+            (Channel::builder(uri), None)
+        }
+        None => (
+            endpoint_settings.endpoint(domain)?,
+            endpoint_settings.proxy(),
+        ),
+    };
+    let connector = Connector::new(settings.configure(endpoint), proxy);
+    Ok(Transport::new(connector, settings, recorder).await?)
+}
+
+/// Carries the deadline of a call to [`Transport`], because tonic drops a `grpc-timeout` metadata.
+pub(crate) const TIMEOUT_HEADER: &str = "x-grpc-gcp-timeout";
+
+/// Carries the number of the attempt of a call to [`Transport`], which records it in the metrics.
+pub(crate) const ATTEMPT_HEADER: &str = "x-grpc-gcp-attempt";
+
+/// Reads the emulator host of a service from the environment.
+pub(crate) fn emulator_host_from_env(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|host| !host.is_empty())
+}
+
+/// How the requests of a client are authenticated.
+pub(crate) enum Credentials {
+    Token(Arc<auth::SharedToken>),
+    Emulator,
+}
+
+impl Credentials {
+    pub(crate) fn authorization(&self) -> Result<MetadataValue<Ascii>, Box<Status>> {
+        match self {
+            Credentials::Token(token) => token.authorization(),
+            Credentials::Emulator => Ok(MetadataValue::from_static(EMULATOR_TOKEN)),
+        }
+    }
 }
 
-pub(crate) async fn get_token(scopes: &[&str]) -> Result<gcp_auth::Token, GCPAuthError> {
-    let authentication_manager = auth::AUTHENTICATION_MANAGER.get().await?;
-    let token = authentication_manager.get_token(scopes).await?;
-    Ok(token)
+/// Returns the credentials of a global client, using the default token provider.
+pub(crate) async fn get_credentials(
+    emulator_host: &RwLock<Option<String>>,
+    scopes: &[&str],
+) -> Result<Credentials, auth::Error> {
+    if emulator_host.read().unwrap().is_some() {
+        return Ok(Credentials::Emulator);
+    }
+    let cache = auth::default_token_cache();
+    Ok(Credentials::Token(cache.get(scopes).await?))
 }
@@ -1,7 +1,129 @@
+mod authorized_user;
+mod error;
+mod http;
+mod impersonated;
+mod metadata_server;
+mod service_account;
+
 use crate::util::init_once::{AsyncInitOnce, AsyncInitializer};
 use async_trait::async_trait;
+pub use authorized_user::AuthorizedUser;
+use chrono::DateTime;
+pub use error::Error;
 use gcp_auth::{AuthenticationManager, GCPAuthError};
+pub use impersonated::ImpersonatedCredentials;
+pub use metadata_server::MetadataServer;
 use once_cell::sync::Lazy;
+pub use service_account::ServiceAccountKey;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock, Weak,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{sync::Mutex, time::delay_for};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    Status,
+};
+
+/// Tokens are fetched again once they expire within this margin.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+/// Tokens are refreshed in the background this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const INITIAL_REFRESH_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_REFRESH_BACKOFF: Duration = Duration::from_secs(60);
+/// Tokens that no client holds stop being refreshed, and are evicted from their cache by their
+/// refresh task, once they have not been requested for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// An OAuth 2.0 access token, sent as a bearer token.
+#[derive(Clone, PartialEq)]
+pub struct Token {
+    access_token: String,
+    expires_at: Option<SystemTime>,
+}
+
+impl Token {
+    pub fn new(access_token: impl Into<String>, expires_at: Option<SystemTime>) -> Token {
+        Token {
+            access_token: access_token.into(),
+            expires_at,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.access_token
+    }
+
+    /// `None` for tokens that are not known to expire.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+
+    fn expires_within(&self, margin: Duration) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= SystemTime::now() + margin,
+            None => false,
+        }
+    }
+}
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("access_token", &"****")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// A source of access tokens for the requests of a client.
+///
+/// Implementations fetch a new token on every call. The clients share the token and refresh it
+/// in the background before it expires.
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    async fn token(&self, scopes: &[&str]) -> Result<Token, Error>;
+}
+
+/// Finds credentials the way `gcp_auth::init` does. This is used unless another provider is set.
+pub struct DefaultTokenProvider {
+    manager: AsyncInitOnce<AuthenticationManagerInitializer>,
+}
+
+impl DefaultTokenProvider {
+    pub fn new() -> DefaultTokenProvider {
+        DefaultTokenProvider {
+            manager: AsyncInitOnce::new(AuthenticationManagerInitializer {}),
+        }
+    }
+}
+
+impl Default for DefaultTokenProvider {
+    fn default() -> Self {
+        DefaultTokenProvider::new()
+    }
+}
+
+#[async_trait]
+impl TokenProvider for DefaultTokenProvider {
+    async fn token(&self, scopes: &[&str]) -> Result<Token, Error> {
+        let manager = self.manager.get().await?;
+        let token = manager.get_token(scopes).await?;
+        Ok(Token::new(token.as_str(), gcp_auth_expiry(&token)))
+    }
+}
+
+/// gcp_auth does not expose when its tokens expire, but it serializes the expiry.
+fn gcp_auth_expiry(token: &gcp_auth::Token) -> Option<SystemTime> {
+    let token = serde_json::to_value(token).ok()?;
+    let expires_at = DateTime::parse_from_rfc3339(token.get("expires_at")?.as_str()?).ok()?;
+    Some(expires_at.into())
+}
 
 pub(crate) struct AuthenticationManagerInitializer {}
 #[async_trait]
@@ -13,5 +135,349 @@ impl AsyncInitializer for AuthenticationManagerInitializer {
     }
 }
 
-pub(crate) static AUTHENTICATION_MANAGER: Lazy<AsyncInitOnce<AuthenticationManagerInitializer>> =
-    Lazy::new(|| AsyncInitOnce::new(AuthenticationManagerInitializer {}));
+/// A bearer token that is used as it is, for example one obtained by other means.
+pub struct StaticToken {
+    token: Token,
+}
+
+impl StaticToken {
+    pub fn new(access_token: impl Into<String>) -> StaticToken {
+        StaticToken {
+            token: Token::new(access_token, None),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenProvider for StaticToken {
+    async fn token(&self, _scopes: &[&str]) -> Result<Token, Error> {
+        Ok(self.token.clone())
+    }
+}
+
+/// The shared tokens of a cache, by their space separated scopes.
+type SharedTokens = RwLock<HashMap<String, Arc<SharedToken>>>;
+
+/// Caches the tokens of a provider per set of scopes.
+pub(crate) struct TokenCache {
+    provider: Arc<dyn TokenProvider>,
+    tokens: Arc<SharedTokens>,
+}
+
+impl TokenCache {
+    pub(crate) fn new(provider: Arc<dyn TokenProvider>) -> TokenCache {
+        TokenCache {
+            provider,
+            tokens: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the shared token for `scopes`, which holds a valid token when this returns.
+    pub(crate) async fn get(&self, scopes: &[&str]) -> Result<Arc<SharedToken>, Error> {
+        let key = scopes.join(" ");
+        let cached = self.tokens.read().unwrap().get(&key).cloned();
+        let shared = match cached {
+            Some(shared) => shared,
+            None => {
+                let mut tokens = self.tokens.write().unwrap();
+                let entry = tokens.entry(key.clone()).or_insert_with(|| {
+                    Arc::new(SharedToken {
+                        provider: self.provider.clone(),
+                        scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+                        token: RwLock::new(None),
+                        fetching: Mutex::new(()),
+                        refreshing: AtomicBool::new(false),
+                        used_at: RwLock::new(Instant::now()),
+                        cache: Arc::downgrade(&self.tokens),
+                        key,
+                    })
+                });
+                entry.clone()
+            }
+        };
+        *shared.used_at.write().unwrap() = Instant::now();
+        shared.ensure().await?;
+        Ok(shared)
+    }
+}
+
+/// A token shared by every client with the same provider and scopes.
+///
+/// Once fetched, the token is refreshed in the background before it expires, so that requests
+/// only read it.
+pub(crate) struct SharedToken {
+    provider: Arc<dyn TokenProvider>,
+    scopes: Vec<String>,
+    token: RwLock<Option<Token>>,
+    fetching: Mutex<()>,
+    refreshing: AtomicBool,
+    /// When the token was last requested from its cache.
+    used_at: RwLock<Instant>,
+    cache: Weak<SharedTokens>,
+    key: String,
+}
+
+impl SharedToken {
+    /// The value of the `authorization` header.
+    ///
+    /// Fails instead of returning an expired token, which happens only when the background
+    /// refresh has been failing for the whole lifetime of the token.
+    pub(crate) fn authorization(&self) -> Result<MetadataValue<Ascii>, Box<Status>> {
+        let token = self.token.read().unwrap();
+        match token.as_ref() {
+            Some(token) if !token.expires_within(Duration::from_secs(0)) => {
+                MetadataValue::from_str(&format!("Bearer {}", token.as_str())).map_err(|_| {
+                    Box::new(Status::unauthenticated(
+                        "The access token is not a valid header value",
+                    ))
+                })
+            }
+            _ => Err(Box::new(Status::unauthenticated(
+                "The access token has expired and could not be refreshed",
+            ))),
+        }
+    }
+
+    /// Whether the token is held by no more than `owners` references, and has not been requested
+    /// for `idle`.
+    fn is_unused(self: &Arc<Self>, owners: usize, idle: Duration) -> bool {
+        Arc::strong_count(self) <= owners && self.used_at.read().unwrap().elapsed() >= idle
+    }
+
+    fn current(&self) -> Option<Token> {
+        let token = self.token.read().unwrap();
+        token
+            .as_ref()
+            .filter(|token| !token.expires_within(EXPIRY_MARGIN))
+            .cloned()
+    }
+
+    /// Removes the token from its cache if no client holds it and it has been idle for `idle`,
+    /// and returns whether it was removed. The caller holds one reference.
+    fn evict(self: &Arc<Self>, idle: Duration) -> bool {
+        match self.cache.upgrade() {
+            Some(tokens) => {
+                // The cache cannot hand out the token while it is locked.
+                let mut tokens = tokens.write().unwrap();
+                let unused = self.is_unused(2, idle);
+                if unused {
+                    tokens.remove(&self.key);
+                }
+                unused
+            }
+            None => self.is_unused(1, idle),
+        }
+    }
+
+    /// Fetches a token unless a valid one is held, and starts refreshing it in the background.
+    ///
+    /// The refresh task starts even if the fetch fails, so that the token is still evicted.
+    pub(crate) async fn ensure(self: &Arc<Self>) -> Result<(), Error> {
+        let mut fetched = Ok(());
+        if self.current().is_none() {
+            let _fetching = self.fetching.lock().await;
+            if self.current().is_none() {
+                fetched = self.fetch().await;
+            }
+        }
+        if !self.refreshing.swap(true, Ordering::SeqCst) {
+            tokio::spawn(refresh(Arc::downgrade(self)));
+        }
+        fetched
+    }
+
+    async fn fetch(&self) -> Result<(), Error> {
+        let scopes: Vec<&str> = self.scopes.iter().map(String::as_str).collect();
+        let token = self.provider.token(&scopes).await?;
+        *self.token.write().unwrap() = Some(token);
+        Ok(())
+    }
+
+    /// How long to wait before refreshing the token, or `None` if it never expires.
+    fn refresh_in(&self) -> Option<Duration> {
+        let token = self.token.read().unwrap();
+        let expires_at = token.as_ref()?.expires_at?;
+        let remaining = expires_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        // Short-lived tokens are refreshed halfway through their remaining lifetime.
+        let wait = if remaining > REFRESH_MARGIN * 2 {
+            remaining - REFRESH_MARGIN
+        } else {
+            remaining / 2
+        };
+        Some(std::cmp::max(wait, MIN_REFRESH_INTERVAL))
+    }
+}
+
+/// Refreshes the token until no client uses it anymore.
+///
+/// A token that is only held by its cache is refreshed until it becomes idle, after which it is
+/// evicted and the next request fetches it again. Tokens that never expire are only checked for
+/// eviction.
+async fn refresh(shared: Weak<SharedToken>) {
+    loop {
+        let wait = match shared.upgrade() {
+            Some(shared) if shared.evict(IDLE_TIMEOUT) => return,
+            Some(shared) => shared.refresh_in(),
+            None => return,
+        };
+        match wait {
+            Some(wait) => delay_for(wait).await,
+            None => {
+                delay_for(IDLE_TIMEOUT).await;
+                continue;
+            }
+        }
+
+        let mut backoff = INITIAL_REFRESH_BACKOFF;
+        loop {
+            let shared = match shared.upgrade() {
+                Some(shared) if shared.evict(IDLE_TIMEOUT) => return,
+                Some(shared) => shared,
+                None => return,
+            };
+            let fetched = {
+                let _fetching = shared.fetching.lock().await;
+                shared.fetch().await
+            };
+            if fetched.is_ok() {
+                break;
+            }
+            drop(shared);
+            delay_for(backoff).await;
+            backoff = std::cmp::min(backoff * 2, MAX_REFRESH_BACKOFF);
+        }
+    }
+}
+
+static DEFAULT_TOKEN_CACHE: Lazy<RwLock<Arc<TokenCache>>> = Lazy::new(|| {
+    let provider = Arc::new(DefaultTokenProvider::new());
+    RwLock::new(Arc::new(TokenCache::new(provider)))
+});
+
+/// Replaces the default credentials, used by the global clients of every service and by the
+/// clients whose [`ClientConfig`](crate::ClientConfig) sets no token provider. Clients keep the
+/// tokens they already hold.
+pub fn set_default_token_provider(provider: impl TokenProvider + 'static) {
+    let cache = Arc::new(TokenCache::new(Arc::new(provider)));
+    *DEFAULT_TOKEN_CACHE.write().unwrap() = cache;
+}
+
+pub(crate) fn default_token_cache() -> Arc<TokenCache> {
+    DEFAULT_TOKEN_CACHE.read().unwrap().clone()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Token, TokenCache, TokenProvider, IDLE_TIMEOUT};
+    use async_trait::async_trait;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime},
+    };
+    use tokio::time::delay_for;
+    use tonic::Code;
+
+    struct CountingProvider {
+        lifetime: Duration,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TokenProvider for CountingProvider {
+        async fn token(&self, _scopes: &[&str]) -> Result<Token, Error> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let expires_at = SystemTime::now() + self.lifetime;
+            Ok(Token::new(calls.to_string(), Some(expires_at)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_cache() {
+        let provider = Arc::new(CountingProvider {
+            lifetime: Duration::from_secs(3600),
+            calls: AtomicUsize::new(0),
+        });
+        let cache = TokenCache::new(provider.clone());
+        assert_eq!("Bearer 1", header(&cache, "a").await);
+        assert_eq!("Bearer 1", header(&cache, "a").await);
+        assert_eq!("Bearer 2", header(&cache, "b").await);
+
+        let provider = Arc::new(CountingProvider {
+            lifetime: Duration::from_secs(10),
+            calls: AtomicUsize::new(0),
+        });
+        let cache = TokenCache::new(provider.clone());
+        assert_eq!("Bearer 1", header(&cache, "a").await);
+        assert_eq!("Bearer 2", header(&cache, "a").await);
+    }
+
+    #[tokio::test]
+    async fn test_background_refresh() {
+        let provider = Arc::new(CountingProvider {
+            lifetime: Duration::from_secs(2),
+            calls: AtomicUsize::new(0),
+        });
+        let cache = TokenCache::new(provider.clone());
+        let shared = cache.get(&["a"]).await.unwrap();
+        assert_eq!(
+            "Bearer 1",
+            shared.authorization().unwrap().to_str().unwrap()
+        );
+        delay_for(Duration::from_millis(1500)).await;
+        assert_eq!(
+            "Bearer 2",
+            shared.authorization().unwrap().to_str().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_token() {
+        let provider = Arc::new(CountingProvider {
+            lifetime: Duration::from_secs(0),
+            calls: AtomicUsize::new(0),
+        });
+        let cache = TokenCache::new(provider);
+        let shared = cache.get(&["a"]).await.unwrap();
+        assert_eq!(
+            Code::Unauthenticated,
+            shared.authorization().unwrap_err().code()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unused_token() {
+        let provider = Arc::new(CountingProvider {
+            lifetime: Duration::from_secs(3600),
+            calls: AtomicUsize::new(0),
+        });
+        let cache = TokenCache::new(provider);
+        let shared = cache.get(&["a"]).await.unwrap();
+        let client = shared.clone();
+        assert!(!shared.evict(Duration::from_secs(0)));
+        drop(client);
+        assert!(!shared.evict(IDLE_TIMEOUT));
+        assert!(shared.evict(Duration::from_secs(0)));
+        assert!(cache.tokens.read().unwrap().is_empty());
+
+        let shared = cache.get(&["a"]).await.unwrap();
+        drop(cache);
+        assert!(shared.evict(Duration::from_secs(0)));
+    }
+
+    async fn header(cache: &TokenCache, scope: &str) -> String {
+        let shared = cache.get(&[scope]).await.unwrap();
+        shared
+            .authorization()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+}
@@ -0,0 +1,80 @@
+use super::{
+    http::{post_form, TokenResponse},
+    Error, Token, TokenProvider,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::Path;
+
+const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Exchanges the refresh token of a user for access tokens, as stored by
+/// `gcloud auth application-default login`.
+///
+/// The scopes are fixed when the user grants access, so the requested scopes are ignored.
+#[derive(Deserialize)]
+pub struct AuthorizedUser {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+}
+
+impl AuthorizedUser {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<AuthorizedUser, Error> {
+        AuthorizedUser::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(json: &[u8]) -> Result<AuthorizedUser, Error> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    fn form(&self) -> [(&str, &str); 4] {
+        [
+            ("grant_type", "refresh_token"),
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+            ("refresh_token", self.refresh_token.as_str()),
+        ]
+    }
+}
+
+#[async_trait]
+impl TokenProvider for AuthorizedUser {
+    async fn token(&self, _scopes: &[&str]) -> Result<Token, Error> {
+        let response: TokenResponse = post_form(TOKEN_URI, &self.form()).await?;
+        Ok(response.into_token())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthorizedUser, Error};
+
+    #[test]
+    fn test_from_bytes() {
+        let json = r#"{
+            "client_id": "id",
+            "client_secret": "secret",
+            "refresh_token": "refresh",
+            "type": "authorized_user"
+        }"#;
+        let user = AuthorizedUser::from_bytes(json.as_bytes()).unwrap();
+        assert_eq!(
+            [
+                ("grant_type", "refresh_token"),
+                ("client_id", "id"),
+                ("client_secret", "secret"),
+                ("refresh_token", "refresh"),
+            ],
+            user.form()
+        );
+
+        let json = r#"{"client_id": "id", "client_secret": "secret"}"#;
+        assert!(matches!(
+            AuthorizedUser::from_bytes(json.as_bytes()),
+            Err(Error::Json(_))
+        ));
+    }
+}
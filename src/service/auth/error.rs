@@ -0,0 +1,62 @@
+use gcp_auth::GCPAuthError;
+
+#[derive(Debug)]
+pub enum Error {
+    GCPAuth(GCPAuthError),
+    Io(std::io::Error),
+    Http(hyper::Error),
+    Json(serde_json::Error),
+    /// The credentials could not be parsed or used.
+    InvalidCredentials(String),
+    /// The token endpoint rejected the request.
+    Token(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::GCPAuth(e) => e.fmt(f),
+            Error::Io(e) => e.fmt(f),
+            Error::Http(e) => e.fmt(f),
+            Error::Json(e) => e.fmt(f),
+            Error::InvalidCredentials(message) => write!(f, "Invalid credentials: {}", message),
+            Error::Token(message) => write!(f, "Failed to fetch a token: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::GCPAuth(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Http(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::InvalidCredentials(_) | Error::Token(_) => None,
+        }
+    }
+}
+
+impl From<GCPAuthError> for Error {
+    fn from(err: GCPAuthError) -> Self {
+        Error::GCPAuth(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
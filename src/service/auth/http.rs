@@ -0,0 +1,74 @@
+use super::{Error, Token};
+use hyper::{client::HttpConnector, header, Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::time::{Duration, SystemTime};
+
+static CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> =
+    Lazy::new(|| Client::builder().build(HttpsConnector::new()));
+
+/// The response of the OAuth 2.0 token endpoint and of the metadata server.
+#[derive(Deserialize)]
+pub(super) struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl TokenResponse {
+    pub(super) fn into_token(self) -> Token {
+        let expires_at = self
+            .expires_in
+            .map(|seconds| SystemTime::now() + Duration::from_secs(seconds));
+        Token::new(self.access_token, expires_at)
+    }
+}
+
+pub(super) async fn post_form<T: DeserializeOwned>(
+    uri: &str,
+    form: &[(&str, &str)],
+) -> Result<T, Error> {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(form)
+        .finish();
+    let request = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .map_err(|e| Error::Token(e.to_string()))?;
+    send(request).await
+}
+
+pub(super) async fn send<T: DeserializeOwned>(request: Request<Body>) -> Result<T, Error> {
+    let response = CLIENT.request(request).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    if !status.is_success() {
+        let body = String::from_utf8_lossy(&body);
+        return Err(Error::Token(format!("{} {}", status, body)));
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::TokenResponse;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_into_token() {
+        let json = r#"{"access_token": "t", "expires_in": 3599, "token_type": "Bearer"}"#;
+        let response: TokenResponse = serde_json::from_str(json).unwrap();
+        let before = SystemTime::now();
+        let token = response.into_token();
+        assert_eq!("t", token.as_str());
+        let expires_at = token.expires_at().unwrap();
+        assert!(expires_at >= before + Duration::from_secs(3599));
+        assert!(expires_at <= SystemTime::now() + Duration::from_secs(3599));
+
+        let response: TokenResponse = serde_json::from_str(r#"{"access_token": "t"}"#).unwrap();
+        assert_eq!(None, response.into_token().expires_at());
+        assert!(serde_json::from_str::<TokenResponse>(r#"{"expires_in": 1}"#).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use super::{http::send, Error, Token, TokenProvider};
+use async_trait::async_trait;
+use chrono::DateTime;
+use hyper::{header, Body, Request};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// Fetches tokens of a service account that the source credentials are allowed to impersonate,
+/// through the IAM Credentials API.
+pub struct ImpersonatedCredentials {
+    source: Arc<dyn TokenProvider>,
+    target_principal: String,
+    delegates: Vec<String>,
+    lifetime: Duration,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateAccessTokenResponse {
+    access_token: String,
+    expire_time: String,
+}
+
+impl ImpersonatedCredentials {
+    /// Impersonates the service account `target_principal` with the tokens of `source`, which
+    /// need the `roles/iam.serviceAccountTokenCreator` role on it.
+    pub fn new(
+        source: impl TokenProvider + 'static,
+        target_principal: impl Into<String>,
+    ) -> ImpersonatedCredentials {
+        ImpersonatedCredentials {
+            source: Arc::new(source),
+            target_principal: target_principal.into(),
+            delegates: Vec::new(),
+            lifetime: Duration::from_secs(3600),
+        }
+    }
+
+    /// The chain of service accounts that delegate the impersonation to the target.
+    pub fn delegates<S: Into<String>>(mut self, delegates: impl IntoIterator<Item = S>) -> Self {
+        self.delegates = delegates.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// How long the tokens are valid for, at most one hour unless extended by an organization policy.
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    fn request_body(&self, scopes: &[&str]) -> serde_json::Value {
+        let delegates: Vec<_> = self
+            .delegates
+            .iter()
+            .map(|delegate| format!("projects/-/serviceAccounts/{}", delegate))
+            .collect();
+        serde_json::json!({
+            "scope": scopes,
+            "delegates": delegates,
+            "lifetime": format!("{}s", self.lifetime.as_secs()),
+        })
+    }
+}
+
+impl GenerateAccessTokenResponse {
+    fn into_token(self) -> Result<Token, Error> {
+        let expires_at = DateTime::parse_from_rfc3339(&self.expire_time)
+            .map_err(|e| Error::Token(format!("invalid expireTime: {}", e)))?;
+        Ok(Token::new(self.access_token, Some(expires_at.into())))
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ImpersonatedCredentials {
+    async fn token(&self, scopes: &[&str]) -> Result<Token, Error> {
+        let source = self.source.token(&[CLOUD_PLATFORM_SCOPE]).await?;
+        let uri = format!(
+            "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/{}:generateAccessToken",
+            self.target_principal
+        );
+        let body = self.request_body(scopes);
+        let request = Request::post(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", source.as_str()))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&body)?))
+            .map_err(|e| Error::Token(e.to_string()))?;
+        let response: GenerateAccessTokenResponse = send(request).await?;
+        response.into_token()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Error, GenerateAccessTokenResponse, ImpersonatedCredentials, Token, TokenProvider,
+    };
+    use async_trait::async_trait;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    struct Source;
+
+    #[async_trait]
+    impl TokenProvider for Source {
+        async fn token(&self, _scopes: &[&str]) -> Result<Token, Error> {
+            Ok(Token::new("source", None))
+        }
+    }
+
+    #[test]
+    fn test_request_body() {
+        let credentials = ImpersonatedCredentials::new(Source, "target@p.iam.gserviceaccount.com")
+            .delegates(vec!["a@p.iam.gserviceaccount.com"])
+            .lifetime(Duration::from_secs(600));
+        assert_eq!(
+            serde_json::json!({
+                "scope": ["s"],
+                "delegates": ["projects/-/serviceAccounts/a@p.iam.gserviceaccount.com"],
+                "lifetime": "600s",
+            }),
+            credentials.request_body(&["s"])
+        );
+    }
+
+    #[test]
+    fn test_into_token() {
+        let json = r#"{"accessToken": "t", "expireTime": "2021-01-01T00:00:01Z"}"#;
+        let response: GenerateAccessTokenResponse = serde_json::from_str(json).unwrap();
+        let token = response.into_token().unwrap();
+        assert_eq!("t", token.as_str());
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(1_609_459_201)),
+            token.expires_at()
+        );
+
+        let json = r#"{"accessToken": "t", "expireTime": "tomorrow"}"#;
+        let response: GenerateAccessTokenResponse = serde_json::from_str(json).unwrap();
+        assert!(matches!(response.into_token(), Err(Error::Token(_))));
+    }
+}
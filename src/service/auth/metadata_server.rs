@@ -0,0 +1,77 @@
+use super::{
+    http::{send, TokenResponse},
+    Error, Token, TokenProvider,
+};
+use async_trait::async_trait;
+use hyper::{Body, Request};
+
+const DEFAULT_HOST: &str = "metadata.google.internal";
+
+/// Fetches tokens for the service account attached to the Compute Engine instance, Cloud Run
+/// service or other Google Cloud environment the process runs in.
+pub struct MetadataServer {
+    host: String,
+    service_account: String,
+}
+
+impl MetadataServer {
+    pub fn new() -> MetadataServer {
+        MetadataServer {
+            host: std::env::var("GCE_METADATA_HOST").unwrap_or_else(|_| DEFAULT_HOST.into()),
+            service_account: "default".into(),
+        }
+    }
+
+    /// Uses another service account attached to the instance than the default one.
+    pub fn service_account(mut self, email: impl Into<String>) -> Self {
+        self.service_account = email.into();
+        self
+    }
+
+    fn uri(&self, scopes: &[&str]) -> String {
+        format!(
+            "http://{}/computeMetadata/v1/instance/service-accounts/{}/token?scopes={}",
+            self.host,
+            self.service_account,
+            scopes.join(",")
+        )
+    }
+}
+
+impl Default for MetadataServer {
+    fn default() -> Self {
+        MetadataServer::new()
+    }
+}
+
+#[async_trait]
+impl TokenProvider for MetadataServer {
+    async fn token(&self, scopes: &[&str]) -> Result<Token, Error> {
+        let request = Request::get(self.uri(scopes))
+            .header("Metadata-Flavor", "Google")
+            .body(Body::empty())
+            .map_err(|e| Error::Token(e.to_string()))?;
+        let response: TokenResponse = send(request).await?;
+        Ok(response.into_token())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::MetadataServer;
+
+    #[test]
+    fn test_uri() {
+        let server = MetadataServer {
+            host: "localhost:8080".into(),
+            service_account: "default".into(),
+        }
+        .service_account("sa@p.iam.gserviceaccount.com");
+        assert_eq!(
+            "http://localhost:8080/computeMetadata/v1/instance/service-accounts/sa@p.iam.gserviceaccount.com/token?scopes=a,b",
+            server.uri(&["a", "b"])
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use crate::Error;
+use http::Uri;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use tower_service::Service;
+
+/// The longest response to a `CONNECT` request that is read from a proxy.
+const MAX_PROXY_RESPONSE: usize = 8 * 1024;
+
+/// Where the channels of a service connect to, when it is not the default endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointSettings {
+    /// The `host:port` to connect to instead of the domain of the service, such as a regional
+    /// endpoint like `us-east1-pubsub.googleapis.com:443`.
+    pub address: Option<String>,
+    /// The name the server certificate is verified against, when it differs from the host of
+    /// `address`.
+    pub tls_domain: Option<String>,
+    /// A PEM encoded CA certificate that is trusted in addition to the system roots.
+    pub ca_certificate: Option<Vec<u8>>,
+    /// An HTTP proxy, such as `http://proxy:3128`, that is asked to tunnel every connection.
+    pub proxy: Option<String>,
+}
+
+impl EndpointSettings {
+    /// The endpoint of a service at `domain`, connected over TLS.
+    pub(crate) fn endpoint(&self, domain: &str) -> Result<Endpoint, Error> {
+        let address = self.address.as_deref().unwrap_or(domain);
+        let uri: Uri = ["https://", address]
+            .concat()
+            .parse()
+            .map_err(|e| Error::InvalidEndpoint(format!("{:?}: {}", address, e)))?;
+        let tls_domain = match &self.tls_domain {
+            Some(tls_domain) => tls_domain.clone(),
+            None => uri.host().unwrap_or(domain).to_string(),
+        };
+        let mut tls = ClientTlsConfig::new().domain_name(tls_domain);
+        if let Some(pem) = &self.ca_certificate {
+            tls = tls.ca_certificate(Certificate::from_pem(pem));
+        }
+        Ok(Channel::builder(uri).tls_config(tls)?)
+    }
+
+    pub(crate) fn proxy(&self) -> Option<ProxyConnector> {
+        let proxy = self.proxy.as_ref()?;
+        let address = match proxy
+            .parse::<Uri>()
+            .ok()
+            .and_then(|uri| uri.into_parts().authority)
+        {
+            Some(authority) if authority.port().is_some() => authority.to_string(),
+            Some(authority) => format!("{}:80", authority),
+            None => proxy.clone(),
+        };
+        Some(ProxyConnector { address })
+    }
+}
+
+/// Opens connections through an HTTP proxy with `CONNECT` requests.
+#[derive(Debug, Clone)]
+pub(crate) struct ProxyConnector {
+    address: String,
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<TcpStream, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let address = self.address.clone();
+        Box::pin(async move {
+            let host = uri
+                .host()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing host"))?;
+            let port = uri.port_u16().unwrap_or(443);
+            let mut stream = TcpStream::connect(address.as_str()).await?;
+            let request = format!(
+                "CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n\r\n",
+                host, port
+            );
+            stream.write_all(request.as_bytes()).await?;
+            let response = read_response(&mut stream).await?;
+            check_response(&response)?;
+            Ok(stream)
+        })
+    }
+}
+
+/// Reads the head of the response of a proxy, without reading past it into the tunnel.
+async fn read_response(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_PROXY_RESPONSE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The response of the proxy is too long",
+            ));
+        }
+        let mut byte = [0];
+        if stream.read(&mut byte).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        response.push(byte[0]);
+    }
+    Ok(response)
+}
+
+fn check_response(response: &[u8]) -> io::Result<()> {
+    let response = String::from_utf8_lossy(response);
+    let status_line = response.lines().next().unwrap_or("");
+    let status = status_line.split_whitespace().nth(1);
+    if status == Some("200") {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("The proxy refused to connect: {}", status_line),
+        ))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{check_response, EndpointSettings};
+
+    #[test]
+    fn test_endpoint() {
+        let settings = EndpointSettings {
+            address: Some("us-east1-pubsub.googleapis.com:443".into()),
+            ..EndpointSettings::default()
+        };
+        let endpoint = settings.endpoint("pubsub.googleapis.com").unwrap();
+        assert_eq!(
+            "https://us-east1-pubsub.googleapis.com:443/",
+            endpoint.uri().to_string()
+        );
+        let endpoint = EndpointSettings::default()
+            .endpoint("pubsub.googleapis.com")
+            .unwrap();
+        assert_eq!("https://pubsub.googleapis.com/", endpoint.uri().to_string());
+
+        let settings = EndpointSettings {
+            address: Some("pubsub googleapis com".into()),
+            ..EndpointSettings::default()
+        };
+        assert!(settings.endpoint("pubsub.googleapis.com").is_err());
+    }
+
+    #[test]
+    fn test_proxy() {
+        let proxy = |proxy: &str| {
+            let settings = EndpointSettings {
+                proxy: Some(proxy.into()),
+                ..EndpointSettings::default()
+            };
+            settings.proxy().unwrap().address
+        };
+        assert_eq!("proxy:3128", proxy("http://proxy:3128"));
+        assert_eq!("proxy:80", proxy("http://proxy"));
+        assert_eq!("proxy:3128", proxy("proxy:3128"));
+        assert!(EndpointSettings::default().proxy().is_none());
+
+        assert!(check_response(b"HTTP/1.1 200 Connection established\r\n\r\n").is_ok());
+        assert!(check_response(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").is_err());
+    }
+}
@@ -1,28 +1,44 @@
-mod error;
+pub mod error;
 mod serde_properties;
 
+#[cfg(feature = "json")]
+use crate::json::{self, JsonOptions};
 use crate::{
-    config::project_id,
+    client::project_id,
     proto::google::datastore::v1::{
         self as datastore, datastore_client::DatastoreClient, key::path_element::IdType,
         key::PathElement, LookupRequest, PartitionId,
     },
+    retry,
     serde_properties::deserializer,
+    CallOptions, Client,
 };
 pub use error::Error;
+#[cfg(feature = "json")]
+use std::convert::TryFrom;
 
-const DOMAIN: &str = "datastore.googleapis.com";
+const SERVICE: crate::Service = crate::Service::Datastore;
 const SCOPE: &str = "https://www.googleapis.com/auth/datastore";
 define_client!(DatastoreClient);
 
 pub struct Datastore {
     namespace_id: String,
+    client: Option<Client>,
 }
 
 impl Datastore {
     pub fn new(namespace: Option<String>) -> Datastore {
         Datastore {
             namespace_id: namespace.map(|s| s.into()).unwrap_or("".into()),
+            client: None,
+        }
+    }
+
+    /// Accesses the project of `client` instead of the one set with `grpc_gcp::init`.
+    pub fn with_client(client: &Client, namespace: Option<String>) -> Datastore {
+        Datastore {
+            client: Some(client.clone()),
+            ..Datastore::new(namespace)
         }
     }
 
@@ -34,7 +50,7 @@ impl Datastore {
     ) -> Key {
         let path = path(kind, IdType::Name(name.into()), parent);
         Key(datastore::Key {
-            partition_id: Some(partition_id(self.namespace_id.clone())),
+            partition_id: Some(self.partition_id()),
             path: path,
         })
     }
@@ -42,7 +58,7 @@ impl Datastore {
     pub fn id_key(&self, kind: impl Into<String>, id: i64, parent: Option<Key>) -> Key {
         let path = path(kind, IdType::Id(id), parent);
         Key(datastore::Key {
-            partition_id: Some(partition_id(self.namespace_id.clone())),
+            partition_id: Some(self.partition_id()),
             path: path,
         })
     }
@@ -51,13 +67,29 @@ impl Datastore {
     where
         T: serde::Deserialize<'de>,
     {
-        let mut client = DatastoreClient::get().await?;
-        let request = tonic::Request::new(LookupRequest {
-            project_id: project_id().to_string(),
+        self.get_with_options(key, CallOptions::default()).await
+    }
+
+    pub async fn get_with_options<'de, T>(&self, key: Key, options: CallOptions) -> Result<T, Error>
+    where
+        T: serde::Deserialize<'de>,
+    {
+        let client = DatastoreClient::get_with(self.client.as_ref()).await?;
+        let project_id = project_id(self.client.as_ref());
+        let request = LookupRequest {
+            project_id: project_id.to_string(),
             keys: vec![key.0.clone()],
             ..Default::default()
-        });
-        let response = client.lookup(request).await?;
+        };
+        let options = options.resource("project_id", project_id);
+        let response = retry::call(
+            &options,
+            self.client.as_ref(),
+            &client,
+            request,
+            |mut client, request| async move { client.lookup(request).await },
+        )
+        .await?;
         let mut found = response.into_inner().found;
         if found.is_empty() {
             Err(Error::NotFound(key))
@@ -67,16 +99,32 @@ impl Datastore {
             )?)
         }
     }
+
+    fn partition_id(&self) -> PartitionId {
+        PartitionId {
+            project_id: project_id(self.client.as_ref()).into(),
+            namespace_id: self.namespace_id.clone(),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct Key(datastore::Key);
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key(pub(crate) datastore::Key);
 
-fn partition_id(namespace_id: String) -> PartitionId {
-    PartitionId {
-        project_id: project_id().into(),
-        namespace_id: namespace_id,
-    }
+/// Converts an entity into JSON, as described in [`json`](crate::json).
+///
+/// The entity is read as a [`Value`](crate::Value), which keeps the types of its properties, with
+/// `Datastore::get::<Value>`. Fails for references, which Datastore cannot hold.
+#[cfg(feature = "json")]
+pub fn to_json(entity: &crate::Value, options: &JsonOptions) -> Result<serde_json::Value, Error> {
+    let entity = datastore::Value::try_from(entity.clone())?;
+    Ok(json::to_json(entity, options))
+}
+
+/// Reads an entity from JSON, as described in [`json`](crate::json).
+#[cfg(feature = "json")]
+pub fn from_json(json: serde_json::Value, options: &JsonOptions) -> Result<crate::Value, Error> {
+    Ok(json::from_json::<datastore::Value>(json, options)?.into())
 }
 
 fn path(kind: impl Into<String>, id_type: IdType, parent: Option<Key>) -> Vec<PathElement> {
@@ -90,3 +138,23 @@ fn path(kind: impl Into<String>, id_type: IdType, parent: Option<Key>) -> Vec<Pa
     });
     path
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::Datastore;
+    use crate::{Client, ClientConfig};
+
+    #[test]
+    fn test_keys_of_client() {
+        let first = Datastore::with_client(&Client::new(ClientConfig::new("first")), None);
+        let second = Datastore::with_client(&Client::new(ClientConfig::new("second")), None);
+        let project_id = |datastore: &Datastore| {
+            let key = datastore.id_key("Kind", 1, None);
+            key.0.partition_id.unwrap().project_id
+        };
+        assert_eq!("first", project_id(&first));
+        assert_eq!("second", project_id(&second));
+    }
+}
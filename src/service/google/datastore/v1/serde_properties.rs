@@ -1,2 +1 @@
 mod deserializer;
-
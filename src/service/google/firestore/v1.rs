@@ -5,12 +5,52 @@ mod serde_fields;
 pub use error::Error;
 use models::CollectionReference;
 
-use crate::proto::google::firestore::v1::firestore_client::FirestoreClient;
+#[cfg(feature = "json")]
+use crate::{
+    json::{self, JsonOptions},
+    proto::google::firestore::v1 as firestore,
+};
+use crate::{proto::google::firestore::v1::firestore_client::FirestoreClient, Client};
+#[cfg(feature = "json")]
+use std::convert::TryFrom;
 
-const DOMAIN: &str = "firestore.googleapis.com";
+const SERVICE: crate::Service = crate::Service::Firestore;
 const SCOPE: &str = "https://www.googleapis.com/auth/datastore";
 define_client!(FirestoreClient);
 
 pub fn collection(id: impl Into<String>) -> CollectionReference {
-    CollectionReference::new(id, None)
+    CollectionReference::root(id, None)
+}
+
+/// The documents of the project of a [`Client`], instead of the one set with `grpc_gcp::init`.
+pub struct Firestore {
+    client: Client,
+}
+
+impl Firestore {
+    pub fn new(client: &Client) -> Firestore {
+        Firestore {
+            client: client.clone(),
+        }
+    }
+
+    pub fn collection(&self, id: impl Into<String>) -> CollectionReference {
+        CollectionReference::root(id, Some(self.client.clone()))
+    }
+}
+
+/// Converts a document into JSON, as described in [`json`](crate::json).
+///
+/// The document is read as a [`Value`](crate::Value), which keeps the types of its fields, with
+/// `DocumentReference::get::<Value>`. Fails for keys, which Firestore cannot hold.
+#[cfg(feature = "json")]
+pub fn to_json(document: &crate::Value, options: &JsonOptions) -> Result<serde_json::Value, Error> {
+    let document = firestore::Value::try_from(document.clone())?;
+    Ok(json::to_json(document, options))
+}
+
+/// Reads a document from JSON, as described in [`json`](crate::json).
+#[cfg(feature = "json")]
+pub fn from_json(json: serde_json::Value, options: &JsonOptions) -> Result<crate::Value, Error> {
+    Ok(json::from_json::<firestore::Value>(json, options)?.into())
 }
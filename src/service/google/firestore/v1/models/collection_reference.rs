@@ -1,19 +1,34 @@
 use super::document_reference::DocumentReference;
+use crate::Client;
 
 #[derive(Clone)]
 pub struct CollectionReference {
     id: String,
     parent: Option<Box<DocumentReference>>,
+    client: Option<Client>,
 }
 
 impl CollectionReference {
-    pub(crate) fn new(id: impl Into<String>, parent: Option<&DocumentReference>) -> Self {
+    pub(crate) fn root(id: impl Into<String>, client: Option<Client>) -> Self {
         CollectionReference {
             id: id.into(),
-            parent: parent.map(|p| Box::new(p.clone())),
+            parent: None,
+            client,
         }
     }
 
+    pub(crate) fn new(id: impl Into<String>, parent: &DocumentReference) -> Self {
+        CollectionReference {
+            id: id.into(),
+            parent: Some(Box::new(parent.clone())),
+            client: parent.client().cloned(),
+        }
+    }
+
+    pub(crate) fn client(&self) -> Option<&Client> {
+        self.client.as_ref()
+    }
+
     pub fn doc(&self, id: impl Into<String>) -> DocumentReference {
         DocumentReference::new(id.into(), self)
     }
@@ -1,8 +1,10 @@
 use super::collection_reference::CollectionReference;
 use crate::{
-    config::project_id,
+    client::project_id,
     proto::google::firestore::v1::{firestore_client::FirestoreClient, GetDocumentRequest},
+    retry,
     serde_properties::deserializer::deserialize,
+    CallOptions, Client,
 };
 use serde::Deserialize;
 
@@ -21,7 +23,11 @@ impl DocumentReference {
     }
 
     pub fn collection(&self, id: impl Into<String>) -> CollectionReference {
-        CollectionReference::new(id.into(), Some(self))
+        CollectionReference::new(id.into(), self)
+    }
+
+    pub(crate) fn client(&self) -> Option<&Client> {
+        self.parent.client()
     }
 
     pub fn path(&self) -> String {
@@ -32,18 +38,35 @@ impl DocumentReference {
     where
         T: Deserialize<'de>,
     {
-        let mut client = FirestoreClient::get().await?;
+        self.get_with_options(CallOptions::default()).await
+    }
 
-        let request = tonic::Request::new(GetDocumentRequest {
+    pub async fn get_with_options<'de, T>(
+        &self,
+        options: CallOptions,
+    ) -> Result<T, super::super::Error>
+    where
+        T: Deserialize<'de>,
+    {
+        let client = FirestoreClient::get_with(self.client()).await?;
+        let request = GetDocumentRequest {
             name: format!(
                 "projects/{}/databases/(default)/documents/{}",
-                project_id(),
+                project_id(self.client()),
                 self.path()
             )
             .to_string(),
             ..Default::default()
-        });
-        let response = client.get_document(request).await?;
+        };
+        let options = options.resource("name", &request.name);
+        let response = retry::call(
+            &options,
+            self.client(),
+            &client,
+            request,
+            |mut client, request| async move { client.get_document(request).await },
+        )
+        .await?;
         let result = deserialize(response.into_inner().fields)?;
         Ok(result)
     }
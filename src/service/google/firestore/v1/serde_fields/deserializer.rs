@@ -5,6 +5,13 @@ use crate::{
 use std::collections::HashMap;
 
 impl serde_properties::LatLngTrait for crate::proto::google::r#type::LatLng {
+    fn new(latitude: f64, longitude: f64) -> Self {
+        crate::proto::google::r#type::LatLng {
+            latitude,
+            longitude,
+        }
+    }
+
     fn get_latitude(&self) -> f64 {
         self.latitude
     }
@@ -63,7 +70,7 @@ impl serde_properties::ValueTrait for Value {
         }
     }
 
-    fn new(value_type: serde_properties::ValueType<Self>) -> Self {
+    fn try_new(value_type: serde_properties::ValueType<Self>) -> Option<Self> {
         let value_type = match value_type {
             serde_properties::ValueType::NullValue(value) => ValueType::NullValue(value),
             serde_properties::ValueType::BooleanValue(value) => ValueType::BooleanValue(value),
@@ -76,11 +83,11 @@ impl serde_properties::ValueTrait for Value {
             serde_properties::ValueType::GeoPointValue(value) => ValueType::GeoPointValue(value),
             serde_properties::ValueType::ArrayValue(value) => ValueType::ArrayValue(value),
             serde_properties::ValueType::MapValue(value) => ValueType::MapValue(value),
-            serde_properties::ValueType::KeyValue(_) => common_panic!(),
+            serde_properties::ValueType::KeyValue(_) => return None,
         };
-        Value {
+        Some(Value {
             value_type: Some(value_type),
-        }
+        })
     }
 
     fn into_value_type(self) -> Option<serde_properties::ValueType<Self>> {
@@ -515,4 +522,66 @@ mod tests {
             deserialize::<ValueHolder<(i32, i32)>, Value>(fields).unwrap_err()
         );
     }
+
+    #[test]
+    fn test_any() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(untagged)]
+        enum Untagged {
+            Integer(i64),
+            Text(String),
+            Child(ValueHolder<i32>),
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(tag = "type")]
+        enum Shape {
+            Circle { radius: f64 },
+            Square { side: f64 },
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            untagged: Vec<Untagged>,
+            shape: Shape,
+            json: serde_json::Value,
+            #[serde(flatten)]
+            rest: HashMap<String, i64>,
+        }
+
+        let untagged = vec![Value::integer(1), Value::string("a"), Value::child1(2)];
+        let shape = HashMap::from_iter(vec![
+            ("type".into(), Value::string("Circle")),
+            ("radius".into(), Value::double(1.5)),
+        ]);
+        let json = HashMap::from_iter(vec![
+            (
+                "a".into(),
+                Value::array(vec![Value::integer(1), Value::new(ValueType::NullValue(0))]),
+            ),
+            ("b".into(), Value::timestamp(1609200000, 0)),
+        ]);
+        let fields: HashMap<String, Value> = HashMap::from_iter(vec![
+            ("untagged".into(), Value::array(untagged)),
+            ("shape".into(), Value::map(shape)),
+            ("json".into(), Value::map(json)),
+            ("x".into(), Value::integer(3)),
+        ]);
+
+        let test: Test = deserialize(fields).unwrap();
+        let expected = Test {
+            untagged: vec![
+                Untagged::Integer(1),
+                Untagged::Text("a".into()),
+                Untagged::Child(ValueHolder { value: 2 }),
+            ],
+            shape: Shape::Circle { radius: 1.5 },
+            json: serde_json::json!({
+                "a": [1, null],
+                "b": { "seconds": 1609200000, "nanos": 0 },
+            }),
+            rest: HashMap::from_iter(vec![("x".into(), 3)]),
+        };
+        assert_eq!(expected, test);
+    }
 }
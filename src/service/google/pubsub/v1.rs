@@ -1,32 +1,112 @@
+mod admin;
+mod avro;
 pub mod error;
 mod models;
+mod protobuf;
+mod publisher;
+pub mod push;
+mod schema;
+mod subscriber;
 
-use crate::config::project_id;
-use error::Error;
-use models::Message;
-use tonic::{Request, Response};
+use crate::{config::project_id, retry, CallOptions};
+pub use admin::{
+    create_snapshot, create_snapshot_with_options, create_subscription,
+    create_subscription_with_options, create_topic, create_topic_with_options, delete_snapshot,
+    delete_snapshot_with_options, delete_subscription, delete_subscription_with_options,
+    delete_topic, delete_topic_with_options, get_subscription, get_subscription_with_options,
+    get_topic, get_topic_with_options, list_snapshots, list_snapshots_with_options,
+    list_subscriptions, list_subscriptions_with_options, list_topic_subscriptions,
+    list_topic_subscriptions_with_options, list_topics, list_topics_with_options, seek,
+    seek_with_options, update_subscription, update_subscription_with_options,
+};
+pub use error::Error;
+pub use models::{
+    DeadLetterPolicy, Encoding, ExpirationPolicy, Message, MessageBuilder, OidcToken, PushConfig,
+    ReceivedMessage, RetryPolicy, Schema, SchemaSettings, SchemaType, SeekTarget, Snapshot,
+    Subscription, SubscriptionUpdate, Topic,
+};
+pub use publisher::{BatchSettings, Publisher};
+pub use schema::{
+    create_schema, create_schema_with_options, delete_schema, delete_schema_with_options,
+    get_schema, get_schema_with_options, list_schemas, list_schemas_with_options, validate_message,
+    validate_message_with_options, validate_schema, validate_schema_with_options,
+};
+pub use subscriber::{
+    acknowledge, acknowledge_with_options, modify_ack_deadline, modify_ack_deadline_with_options,
+    pull, pull_with_options, Subscriber, SubscriberSettings,
+};
+use tonic::Response;
 
 use crate::proto::google::pubsub::v1::{
-    publisher_client::PublisherClient, PublishRequest, PublishResponse,
+    publisher_client::PublisherClient, schema_service_client::SchemaServiceClient,
+    subscriber_client::SubscriberClient, PublishRequest, PublishResponse,
 };
 
-const DOMAIN: &str = "pubsub.googleapis.com";
+const SERVICE: crate::Service = crate::Service::PubSub;
 const SCOPE: &str = "https://www.googleapis.com/auth/pubsub";
 
-define_client!(PublisherClient);
+define_client!(PublisherClient, SubscriberClient, SchemaServiceClient);
 
 pub async fn publish(
     topic: impl Into<String>,
     message: Message,
-) -> Result<Response<PublishResponse>, Box<dyn std::error::Error>> {
+) -> Result<Response<PublishResponse>, Error> {
+    publish_with_options(topic, message, CallOptions::default()).await
+}
+
+pub async fn publish_with_options(
+    topic: impl Into<String>,
+    message: Message,
+    options: CallOptions,
+) -> Result<Response<PublishResponse>, Error> {
     let mut client = PublisherClient::get().await?;
 
+    let topic = topic_name(topic);
     let message = PublishRequest {
-        topic: format!("projects/{}/topics/{}", project_id(), topic.into()),
-        messages: vec![message.to_tonic()],
+        topic: topic.clone(),
+        messages: vec![message.into_tonic()],
     };
 
-    let request = Request::new(message);
-    let response = client.publish(request).await?;
+    let options = options.resource("topic", &topic);
+    let response = retry::send(&options, message, |request| client.publish(request)).await?;
     Ok(response)
 }
+
+/// The resource name of a topic.
+pub(crate) fn topic_name(topic: impl Into<String>) -> String {
+    resource_name("topics", topic.into())
+}
+
+/// The resource name of a snapshot.
+pub(crate) fn snapshot_name(snapshot: impl Into<String>) -> String {
+    resource_name("snapshots", snapshot.into())
+}
+
+/// The resource name of a schema.
+pub(crate) fn schema_name(schema: impl Into<String>) -> String {
+    resource_name("schemas", schema.into())
+}
+
+/// The resource name of a subscription.
+pub(crate) fn subscription_name(subscription: impl Into<String>) -> String {
+    resource_name("subscriptions", subscription.into())
+}
+
+fn resource_name(collection: &str, id: String) -> String {
+    qualify(collection, id, || project_id())
+}
+
+pub(crate) fn resource_name_in(project_id: &str, collection: &str, id: String) -> String {
+    qualify(collection, id, || project_id)
+}
+
+/// Resolves the id of a resource in `collection` of the project into its resource name.
+/// Names that are already fully qualified are returned as they are, without looking up the
+/// project.
+fn qualify<'a>(collection: &str, id: String, project_id: impl FnOnce() -> &'a str) -> String {
+    if id.starts_with("projects/") {
+        id
+    } else {
+        format!("projects/{}/{}/{}", project_id(), collection, id)
+    }
+}
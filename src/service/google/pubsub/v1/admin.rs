@@ -0,0 +1,393 @@
+use super::{
+    error::Error,
+    models::{SeekTarget, Snapshot, Subscription, SubscriptionUpdate, Topic},
+    snapshot_name, subscription_name, topic_name,
+};
+use crate::{
+    config::project_id,
+    proto::google::pubsub::v1::{
+        publisher_client::PublisherClient, seek_request, subscriber_client::SubscriberClient,
+        CreateSnapshotRequest, DeleteSnapshotRequest, DeleteSubscriptionRequest,
+        DeleteTopicRequest, GetSubscriptionRequest, GetTopicRequest, ListSnapshotsRequest,
+        ListSubscriptionsRequest, ListTopicSubscriptionsRequest, ListTopicsRequest, SeekRequest,
+        UpdateSubscriptionRequest,
+    },
+    retry,
+    util::time::to_proto_timestamp,
+    CallOptions,
+};
+use prost_types::FieldMask;
+
+pub async fn create_topic(topic: impl Into<Topic>) -> Result<Topic, Error> {
+    create_topic_with_options(topic, CallOptions::default()).await
+}
+
+pub async fn create_topic_with_options(
+    topic: impl Into<Topic>,
+    options: CallOptions,
+) -> Result<Topic, Error> {
+    let mut client = PublisherClient::get().await?;
+    let request = topic.into().into_tonic();
+    let options = options.resource("name", &request.name);
+    let response = retry::send(&options, request, |request| client.create_topic(request)).await?;
+    Ok(Topic::from_tonic(response.into_inner()))
+}
+
+pub async fn get_topic(topic: impl Into<String>) -> Result<Topic, Error> {
+    get_topic_with_options(topic, CallOptions::default()).await
+}
+
+pub async fn get_topic_with_options(
+    topic: impl Into<String>,
+    options: CallOptions,
+) -> Result<Topic, Error> {
+    let client = PublisherClient::get().await?;
+    let request = GetTopicRequest {
+        topic: topic_name(topic),
+    };
+    let options = options.resource("topic", &request.topic);
+    let response = retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.get_topic(request).await },
+    )
+    .await?;
+    Ok(Topic::from_tonic(response.into_inner()))
+}
+
+pub async fn delete_topic(topic: impl Into<String>) -> Result<(), Error> {
+    delete_topic_with_options(topic, CallOptions::default()).await
+}
+
+pub async fn delete_topic_with_options(
+    topic: impl Into<String>,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let client = PublisherClient::get().await?;
+    let request = DeleteTopicRequest {
+        topic: topic_name(topic),
+    };
+    let options = options.resource("topic", &request.topic);
+    retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.delete_topic(request).await },
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn list_topics() -> Result<Vec<Topic>, Error> {
+    list_topics_with_options(CallOptions::default()).await
+}
+
+/// Lists the topics of the project, with `options` applied to the request of every page.
+pub async fn list_topics_with_options(options: CallOptions) -> Result<Vec<Topic>, Error> {
+    let client = PublisherClient::get().await?;
+    let project = format!("projects/{}", project_id());
+    let options = options.resource("project", &project);
+    let mut topics = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let request = ListTopicsRequest {
+            project: project.clone(),
+            page_token,
+            ..Default::default()
+        };
+        let response = retry::call(
+            &options,
+            None,
+            &client,
+            request,
+            |mut client, request| async move { client.list_topics(request).await },
+        )
+        .await?
+        .into_inner();
+        topics.extend(response.topics.into_iter().map(Topic::from_tonic));
+        if response.next_page_token.is_empty() {
+            return Ok(topics);
+        }
+        page_token = response.next_page_token;
+    }
+}
+
+/// Returns the resource names of the subscriptions attached to `topic`.
+pub async fn list_topic_subscriptions(topic: impl Into<String>) -> Result<Vec<String>, Error> {
+    list_topic_subscriptions_with_options(topic, CallOptions::default()).await
+}
+
+pub async fn list_topic_subscriptions_with_options(
+    topic: impl Into<String>,
+    options: CallOptions,
+) -> Result<Vec<String>, Error> {
+    let client = PublisherClient::get().await?;
+    let topic = topic_name(topic);
+    let options = options.resource("topic", &topic);
+    let mut subscriptions = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let request = ListTopicSubscriptionsRequest {
+            topic: topic.clone(),
+            page_token,
+            ..Default::default()
+        };
+        let response = retry::call(
+            &options,
+            None,
+            &client,
+            request,
+            |mut client, request| async move { client.list_topic_subscriptions(request).await },
+        )
+        .await?
+        .into_inner();
+        subscriptions.extend(response.subscriptions);
+        if response.next_page_token.is_empty() {
+            return Ok(subscriptions);
+        }
+        page_token = response.next_page_token;
+    }
+}
+
+pub async fn create_subscription(subscription: Subscription) -> Result<Subscription, Error> {
+    create_subscription_with_options(subscription, CallOptions::default()).await
+}
+
+pub async fn create_subscription_with_options(
+    subscription: Subscription,
+    options: CallOptions,
+) -> Result<Subscription, Error> {
+    let mut client = SubscriberClient::get().await?;
+    let request = subscription.into_tonic();
+    let options = options.resource("name", &request.name);
+    let response = retry::send(&options, request, |request| {
+        client.create_subscription(request)
+    })
+    .await?;
+    Ok(Subscription::from_tonic(response.into_inner()))
+}
+
+pub async fn get_subscription(subscription: impl Into<String>) -> Result<Subscription, Error> {
+    get_subscription_with_options(subscription, CallOptions::default()).await
+}
+
+pub async fn get_subscription_with_options(
+    subscription: impl Into<String>,
+    options: CallOptions,
+) -> Result<Subscription, Error> {
+    let client = SubscriberClient::get().await?;
+    let request = GetSubscriptionRequest {
+        subscription: subscription_name(subscription),
+    };
+    let options = options.resource("subscription", &request.subscription);
+    let response = retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.get_subscription(request).await },
+    )
+    .await?;
+    Ok(Subscription::from_tonic(response.into_inner()))
+}
+
+pub async fn update_subscription(
+    subscription: impl Into<String>,
+    update: SubscriptionUpdate,
+) -> Result<Subscription, Error> {
+    update_subscription_with_options(subscription, update, CallOptions::default()).await
+}
+
+pub async fn update_subscription_with_options(
+    subscription: impl Into<String>,
+    update: SubscriptionUpdate,
+    options: CallOptions,
+) -> Result<Subscription, Error> {
+    let mut client = SubscriberClient::get().await?;
+    let (subscription, paths) = update.into_tonic(subscription_name(subscription));
+    let options = options.resource("subscription.name", &subscription.name);
+    let request = UpdateSubscriptionRequest {
+        subscription: Some(subscription),
+        update_mask: Some(FieldMask { paths }),
+    };
+    let response = retry::send(&options, request, |request| {
+        client.update_subscription(request)
+    })
+    .await?;
+    Ok(Subscription::from_tonic(response.into_inner()))
+}
+
+pub async fn delete_subscription(subscription: impl Into<String>) -> Result<(), Error> {
+    delete_subscription_with_options(subscription, CallOptions::default()).await
+}
+
+pub async fn delete_subscription_with_options(
+    subscription: impl Into<String>,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let client = SubscriberClient::get().await?;
+    let request = DeleteSubscriptionRequest {
+        subscription: subscription_name(subscription),
+    };
+    let options = options.resource("subscription", &request.subscription);
+    retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.delete_subscription(request).await },
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn list_subscriptions() -> Result<Vec<Subscription>, Error> {
+    list_subscriptions_with_options(CallOptions::default()).await
+}
+
+/// Lists the subscriptions of the project, with `options` applied to the request of every page.
+pub async fn list_subscriptions_with_options(
+    options: CallOptions,
+) -> Result<Vec<Subscription>, Error> {
+    let client = SubscriberClient::get().await?;
+    let project = format!("projects/{}", project_id());
+    let options = options.resource("project", &project);
+    let mut subscriptions = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let request = ListSubscriptionsRequest {
+            project: project.clone(),
+            page_token,
+            ..Default::default()
+        };
+        let response = retry::call(
+            &options,
+            None,
+            &client,
+            request,
+            |mut client, request| async move { client.list_subscriptions(request).await },
+        )
+        .await?
+        .into_inner();
+        subscriptions.extend(
+            response
+                .subscriptions
+                .into_iter()
+                .map(Subscription::from_tonic),
+        );
+        if response.next_page_token.is_empty() {
+            return Ok(subscriptions);
+        }
+        page_token = response.next_page_token;
+    }
+}
+
+/// Captures the acknowledgement state of `subscription` into a new snapshot.
+pub async fn create_snapshot(
+    snapshot: impl Into<String>,
+    subscription: impl Into<String>,
+) -> Result<Snapshot, Error> {
+    create_snapshot_with_options(snapshot, subscription, CallOptions::default()).await
+}
+
+pub async fn create_snapshot_with_options(
+    snapshot: impl Into<String>,
+    subscription: impl Into<String>,
+    options: CallOptions,
+) -> Result<Snapshot, Error> {
+    let mut client = SubscriberClient::get().await?;
+    let request = CreateSnapshotRequest {
+        name: snapshot_name(snapshot),
+        subscription: subscription_name(subscription),
+        ..Default::default()
+    };
+    let options = options.resource("name", &request.name);
+    let response =
+        retry::send(&options, request, |request| client.create_snapshot(request)).await?;
+    Ok(Snapshot::from_tonic(response.into_inner()))
+}
+
+pub async fn list_snapshots() -> Result<Vec<Snapshot>, Error> {
+    list_snapshots_with_options(CallOptions::default()).await
+}
+
+/// Lists the snapshots of the project, with `options` applied to the request of every page.
+pub async fn list_snapshots_with_options(options: CallOptions) -> Result<Vec<Snapshot>, Error> {
+    let client = SubscriberClient::get().await?;
+    let project = format!("projects/{}", project_id());
+    let options = options.resource("project", &project);
+    let mut snapshots = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let request = ListSnapshotsRequest {
+            project: project.clone(),
+            page_token,
+            ..Default::default()
+        };
+        let response = retry::call(
+            &options,
+            None,
+            &client,
+            request,
+            |mut client, request| async move { client.list_snapshots(request).await },
+        )
+        .await?
+        .into_inner();
+        snapshots.extend(response.snapshots.into_iter().map(Snapshot::from_tonic));
+        if response.next_page_token.is_empty() {
+            return Ok(snapshots);
+        }
+        page_token = response.next_page_token;
+    }
+}
+
+pub async fn delete_snapshot(snapshot: impl Into<String>) -> Result<(), Error> {
+    delete_snapshot_with_options(snapshot, CallOptions::default()).await
+}
+
+pub async fn delete_snapshot_with_options(
+    snapshot: impl Into<String>,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let client = SubscriberClient::get().await?;
+    let request = DeleteSnapshotRequest {
+        snapshot: snapshot_name(snapshot),
+    };
+    let options = options.resource("snapshot", &request.snapshot);
+    retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.delete_snapshot(request).await },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Moves the acknowledgement state of `subscription` to `target`, for example to replay messages.
+pub async fn seek(subscription: impl Into<String>, target: SeekTarget) -> Result<(), Error> {
+    seek_with_options(subscription, target, CallOptions::default()).await
+}
+
+pub async fn seek_with_options(
+    subscription: impl Into<String>,
+    target: SeekTarget,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let mut client = SubscriberClient::get().await?;
+    let target = match target {
+        SeekTarget::Time(time) => seek_request::Target::Time(to_proto_timestamp(time)),
+        SeekTarget::Snapshot(snapshot) => seek_request::Target::Snapshot(snapshot_name(snapshot)),
+    };
+    let request = SeekRequest {
+        subscription: subscription_name(subscription),
+        target: Some(target),
+    };
+    let options = options.resource("subscription", &request.subscription);
+    retry::send(&options, request, |request| client.seek(request)).await?;
+    Ok(())
+}
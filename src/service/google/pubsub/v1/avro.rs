@@ -0,0 +1,512 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// An Avro schema parsed from its JSON definition.
+///
+/// Payloads are checked against the schema and converted into either the Avro JSON encoding or
+/// the Avro binary encoding, which are the two encodings Pub/Sub accepts for Avro schemas.
+#[derive(Debug, Clone)]
+pub(crate) struct AvroSchema {
+    root: Type,
+    names: HashMap<String, Type>,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Record {
+        name: String,
+        fields: Vec<Field>,
+    },
+    Enum {
+        name: String,
+        symbols: Vec<String>,
+    },
+    Array(Box<Type>),
+    Map(Box<Type>),
+    Union(Vec<Type>),
+    Fixed {
+        name: String,
+        size: usize,
+    },
+    /// A reference to a named type defined elsewhere in the schema.
+    Named(String),
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    schema: Type,
+    default: Option<Value>,
+}
+
+impl AvroSchema {
+    pub(crate) fn parse(definition: &str) -> Result<AvroSchema, String> {
+        let json: Value =
+            serde_json::from_str(definition).map_err(|e| format!("Invalid Avro schema: {}", e))?;
+        let mut names = HashMap::new();
+        let root = parse_type(&json, "", &mut names)?;
+        Ok(AvroSchema { root, names })
+    }
+
+    /// Checks `value` against the schema and returns it in the Avro JSON encoding.
+    pub(crate) fn to_json(&self, value: &Value) -> Result<Value, String> {
+        self.canonical(&self.root, value, "$")
+    }
+
+    /// Checks `value` against the schema and returns it in the Avro binary encoding.
+    pub(crate) fn to_binary(&self, value: &Value) -> Result<Vec<u8>, String> {
+        let value = self.to_json(value)?;
+        let mut out = Vec::new();
+        self.write(&self.root, &value, &mut out);
+        Ok(out)
+    }
+
+    fn resolve<'a>(&'a self, ty: &'a Type) -> &'a Type {
+        match ty {
+            Type::Named(name) => &self.names[name],
+            ty => ty,
+        }
+    }
+
+    fn type_name<'a>(&'a self, ty: &'a Type) -> &'a str {
+        match ty {
+            Type::Null => "null",
+            Type::Boolean => "boolean",
+            Type::Int => "int",
+            Type::Long => "long",
+            Type::Float => "float",
+            Type::Double => "double",
+            Type::Bytes => "bytes",
+            Type::String => "string",
+            Type::Record { name, .. } | Type::Enum { name, .. } | Type::Fixed { name, .. } => name,
+            Type::Named(name) => name,
+            Type::Array(_) => "array",
+            Type::Map(_) => "map",
+            Type::Union(_) => "union",
+        }
+    }
+
+    fn describe(&self, ty: &Type) -> String {
+        match self.resolve(ty) {
+            Type::Union(branches) => {
+                let names: Vec<_> = branches.iter().map(|b| self.type_name(b)).collect();
+                format!("one of [{}]", names.join(", "))
+            }
+            Type::Enum { name, symbols } => format!("{} ({})", name, symbols.join(" | ")),
+            Type::Fixed { name, size } => format!("{} ({} bytes)", name, size),
+            ty => self.type_name(ty).to_string(),
+        }
+    }
+
+    /// Converts `value` into the Avro JSON encoding, in which every union value names its branch.
+    fn canonical(&self, ty: &Type, value: &Value, path: &str) -> Result<Value, String> {
+        let mismatch = || {
+            format!(
+                "`{}`: expected {}, found {}",
+                path,
+                self.describe(ty),
+                value
+            )
+        };
+        match (self.resolve(ty), value) {
+            (Type::Null, Value::Null) => Ok(Value::Null),
+            (Type::Boolean, Value::Bool(_)) => Ok(value.clone()),
+            (Type::Int, Value::Number(n)) if matches!(n.as_i64(), Some(n) if i32::MIN as i64 <= n && n <= i32::MAX as i64) => {
+                Ok(value.clone())
+            }
+            (Type::Long, Value::Number(n)) if n.is_i64() => Ok(value.clone()),
+            (Type::Float, Value::Number(_)) => Ok(value.clone()),
+            (Type::Double, Value::Number(_)) => Ok(value.clone()),
+            (Type::String, Value::String(_)) => Ok(value.clone()),
+            (Type::Bytes, _) => bytes_string(value).map(Value::String).ok_or_else(mismatch),
+            (Type::Fixed { size, .. }, _) => match bytes_string(value) {
+                Some(bytes) if bytes.chars().count() == *size => Ok(Value::String(bytes)),
+                _ => Err(mismatch()),
+            },
+            (Type::Enum { symbols, .. }, Value::String(symbol)) if symbols.contains(symbol) => {
+                Ok(value.clone())
+            }
+            (Type::Array(items), Value::Array(values)) => values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| self.canonical(items, v, &format!("{}[{}]", path, i)))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            (Type::Map(values), Value::Object(entries)) => entries
+                .iter()
+                .map(|(k, v)| {
+                    let v = self.canonical(values, v, &format!("{}.{}", path, k))?;
+                    Ok((k.clone(), v))
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::Object),
+            (Type::Record { fields, .. }, Value::Object(entries)) => {
+                if let Some(key) = entries
+                    .keys()
+                    .find(|key| !fields.iter().any(|field| &field.name == *key))
+                {
+                    return Err(format!("`{}`: unknown field {:?}", path, key));
+                }
+                let mut record = Map::new();
+                for field in fields {
+                    let path = format!("{}.{}", path, field.name);
+                    let value = match (entries.get(&field.name), &field.default) {
+                        (Some(value), _) => self.canonical(&field.schema, value, &path)?,
+                        (None, Some(default)) => self.canonical(&field.schema, default, &path)?,
+                        (None, None) => self
+                            .canonical(&field.schema, &Value::Null, &path)
+                            .map_err(|_| format!("`{}`: missing field", path))?,
+                    };
+                    record.insert(field.name.clone(), value);
+                }
+                Ok(Value::Object(record))
+            }
+            (Type::Union(branches), _) => {
+                // A value that is already in the Avro JSON encoding names its branch explicitly.
+                if let Value::Object(wrapped) = value {
+                    if let Some((name, inner)) =
+                        wrapped.iter().next().filter(|_| wrapped.len() == 1)
+                    {
+                        let branch = branches.iter().find(|b| self.type_name(b) == name);
+                        if let Some(branch) = branch {
+                            if let Ok(inner) = self.canonical(branch, inner, path) {
+                                return Ok(self.wrap(branch, inner));
+                            }
+                        }
+                    }
+                }
+                branches
+                    .iter()
+                    .find_map(|branch| {
+                        let inner = self.canonical(branch, value, path).ok()?;
+                        Some(self.wrap(branch, inner))
+                    })
+                    .ok_or_else(mismatch)
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    fn wrap(&self, branch: &Type, value: Value) -> Value {
+        match self.resolve(branch) {
+            Type::Null => Value::Null,
+            branch => {
+                let mut wrapped = Map::new();
+                wrapped.insert(self.type_name(branch).to_string(), value);
+                Value::Object(wrapped)
+            }
+        }
+    }
+
+    /// Writes a value produced by `canonical` in the Avro binary encoding.
+    fn write(&self, ty: &Type, value: &Value, out: &mut Vec<u8>) {
+        match (self.resolve(ty), value) {
+            (Type::Null, _) => {}
+            (Type::Boolean, Value::Bool(b)) => out.push(*b as u8),
+            (Type::Int, Value::Number(n)) | (Type::Long, Value::Number(n)) => {
+                write_long(n.as_i64().unwrap_or_else(|| common_panic!()), out)
+            }
+            (Type::Float, Value::Number(n)) => {
+                let n = n.as_f64().unwrap_or_else(|| common_panic!()) as f32;
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            (Type::Double, Value::Number(n)) => {
+                let n = n.as_f64().unwrap_or_else(|| common_panic!());
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            (Type::Bytes, Value::String(s)) => {
+                write_long(s.chars().count() as i64, out);
+                out.extend(s.chars().map(|c| c as u8));
+            }
+            (Type::Fixed { .. }, Value::String(s)) => out.extend(s.chars().map(|c| c as u8)),
+            (Type::String, Value::String(s)) => {
+                write_long(s.len() as i64, out);
+                out.extend_from_slice(s.as_bytes());
+            }
+            (Type::Enum { symbols, .. }, Value::String(s)) => {
+                let index = symbols.iter().position(|symbol| symbol == s);
+                write_long(index.unwrap_or_else(|| common_panic!()) as i64, out);
+            }
+            (Type::Array(items), Value::Array(values)) => {
+                if !values.is_empty() {
+                    write_long(values.len() as i64, out);
+                    for value in values {
+                        self.write(items, value, out);
+                    }
+                }
+                out.push(0);
+            }
+            (Type::Map(values), Value::Object(entries)) => {
+                if !entries.is_empty() {
+                    write_long(entries.len() as i64, out);
+                    for (key, value) in entries {
+                        write_long(key.len() as i64, out);
+                        out.extend_from_slice(key.as_bytes());
+                        self.write(values, value, out);
+                    }
+                }
+                out.push(0);
+            }
+            (Type::Record { fields, .. }, Value::Object(entries)) => {
+                for field in fields {
+                    self.write(&field.schema, &entries[&field.name], out);
+                }
+            }
+            (Type::Union(branches), _) => {
+                let (index, inner) = match value {
+                    Value::Object(wrapped) if wrapped.len() == 1 => {
+                        let (name, inner) =
+                            wrapped.iter().next().unwrap_or_else(|| common_panic!());
+                        let index = branches.iter().position(|b| self.type_name(b) == name);
+                        (index, inner)
+                    }
+                    _ => {
+                        let index = branches
+                            .iter()
+                            .position(|b| matches!(self.resolve(b), Type::Null));
+                        (index, value)
+                    }
+                };
+                let index = index.unwrap_or_else(|| common_panic!());
+                write_long(index as i64, out);
+                self.write(&branches[index], inner, out);
+            }
+            _ => common_panic!(),
+        }
+    }
+}
+
+fn parse_type(
+    json: &Value,
+    namespace: &str,
+    names: &mut HashMap<String, Type>,
+) -> Result<Type, String> {
+    match json {
+        Value::String(name) => parse_name(name, namespace, names),
+        Value::Array(branches) => branches
+            .iter()
+            .map(|branch| parse_type(branch, namespace, names))
+            .collect::<Result<_, _>>()
+            .map(Type::Union),
+        Value::Object(object) => parse_complex(object, namespace, names),
+        _ => Err(format!("Invalid Avro type {}", json)),
+    }
+}
+
+fn parse_name(name: &str, namespace: &str, names: &HashMap<String, Type>) -> Result<Type, String> {
+    let ty = match name {
+        "null" => Type::Null,
+        "boolean" => Type::Boolean,
+        "int" => Type::Int,
+        "long" => Type::Long,
+        "float" => Type::Float,
+        "double" => Type::Double,
+        "bytes" => Type::Bytes,
+        "string" => Type::String,
+        _ => {
+            let fullname = full_name(name, namespace);
+            if names.contains_key(&fullname) {
+                Type::Named(fullname)
+            } else if names.contains_key(name) {
+                Type::Named(name.to_string())
+            } else {
+                return Err(format!("Unknown Avro type {:?}", name));
+            }
+        }
+    };
+    Ok(ty)
+}
+
+fn parse_complex(
+    object: &Map<String, Value>,
+    namespace: &str,
+    names: &mut HashMap<String, Type>,
+) -> Result<Type, String> {
+    let kind = match object.get("type") {
+        Some(Value::String(kind)) => kind.as_str(),
+        Some(ty) => return parse_type(ty, namespace, names),
+        None => return Err("Avro type without a \"type\" attribute".into()),
+    };
+    match kind {
+        "array" => {
+            let items = object.get("items").ok_or("Avro array without items")?;
+            Ok(Type::Array(Box::new(parse_type(items, namespace, names)?)))
+        }
+        "map" => {
+            let values = object.get("values").ok_or("Avro map without values")?;
+            Ok(Type::Map(Box::new(parse_type(values, namespace, names)?)))
+        }
+        "record" | "error" | "enum" | "fixed" => {
+            let name = object
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("Avro {} without a name", kind))?;
+            let namespace = object
+                .get("namespace")
+                .and_then(Value::as_str)
+                .unwrap_or(namespace);
+            let fullname = full_name(name, namespace);
+            let namespace = match fullname.rfind('.') {
+                Some(index) => fullname[..index].to_string(),
+                None => String::new(),
+            };
+            let ty = match kind {
+                "enum" => {
+                    let symbols = object
+                        .get("symbols")
+                        .and_then(Value::as_array)
+                        .ok_or_else(|| format!("Avro enum {} without symbols", fullname))?;
+                    Type::Enum {
+                        name: fullname.clone(),
+                        symbols: symbols
+                            .iter()
+                            .filter_map(|symbol| symbol.as_str().map(String::from))
+                            .collect(),
+                    }
+                }
+                "fixed" => {
+                    let size = object
+                        .get("size")
+                        .and_then(Value::as_u64)
+                        .ok_or_else(|| format!("Avro fixed {} without a size", fullname))?;
+                    Type::Fixed {
+                        name: fullname.clone(),
+                        size: size as usize,
+                    }
+                }
+                _ => {
+                    // Registered up front so that fields can refer to the record itself.
+                    names.insert(fullname.clone(), Type::Null);
+                    let fields = object
+                        .get("fields")
+                        .and_then(Value::as_array)
+                        .ok_or_else(|| format!("Avro record {} without fields", fullname))?
+                        .iter()
+                        .map(|field| parse_field(field, &namespace, names))
+                        .collect::<Result<_, _>>()?;
+                    Type::Record {
+                        name: fullname.clone(),
+                        fields,
+                    }
+                }
+            };
+            names.insert(fullname, ty.clone());
+            Ok(ty)
+        }
+        // A primitive type with attributes such as `logicalType`.
+        _ => parse_name(kind, namespace, names),
+    }
+}
+
+fn parse_field(
+    json: &Value,
+    namespace: &str,
+    names: &mut HashMap<String, Type>,
+) -> Result<Field, String> {
+    let name = json
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or("Avro field without a name")?;
+    let schema = json
+        .get("type")
+        .ok_or_else(|| format!("Avro field {} without a type", name))?;
+    Ok(Field {
+        name: name.to_string(),
+        schema: parse_type(schema, namespace, names)?,
+        default: json.get("default").cloned(),
+    })
+}
+
+fn full_name(name: &str, namespace: &str) -> String {
+    if name.contains('.') || namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+/// Avro JSON represents bytes as a string of code points from 0 to 255.
+/// Byte arrays as serialized by serde are accepted as well.
+fn bytes_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if s.chars().all(|c| (c as u32) < 256) => Some(s.clone()),
+        Value::Array(values) => values
+            .iter()
+            .map(|v| match v.as_u64() {
+                Some(b) if b < 256 => Some(b as u8 as char),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn write_long(value: i64, out: &mut Vec<u8>) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::AvroSchema;
+    use serde_json::json;
+
+    const USER: &str = r#"{
+        "type": "record",
+        "name": "User",
+        "namespace": "example",
+        "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "name", "type": "string"},
+            {"name": "email", "type": ["null", "string"], "default": null},
+            {"name": "friends", "type": {"type": "array", "items": "User"}, "default": []}
+        ]
+    }"#;
+
+    #[test]
+    fn test_json() {
+        let schema = AvroSchema::parse(USER).unwrap();
+        let value = json!({"id": 1, "name": "a", "email": "a@example.com"});
+        assert_eq!(
+            json!({"id": 1, "name": "a", "email": {"string": "a@example.com"}, "friends": []}),
+            schema.to_json(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_binary() {
+        let schema = AvroSchema::parse(USER).unwrap();
+        let value = json!({"id": -2, "name": "a", "friends": [{"id": 64, "name": ""}]});
+        assert_eq!(
+            vec![3, 2, b'a', 0, 2, 128, 1, 0, 0, 0, 0],
+            schema.to_binary(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let schema = AvroSchema::parse(USER).unwrap();
+        assert_eq!(
+            Err("`$.friends[0].id`: expected long, found \"2\"".to_string()),
+            schema.to_json(&json!({"id": 1, "name": "a", "friends": [{"id": "2", "name": "b"}]}))
+        );
+        assert_eq!(
+            Err("`$.name`: missing field".to_string()),
+            schema.to_json(&json!({"id": 1}))
+        );
+    }
+}
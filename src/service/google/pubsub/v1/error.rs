@@ -1,42 +1,47 @@
-use gcp_auth::GCPAuthError;
+pub use crate::Error;
 
-#[derive(Debug)]
-pub enum Error {
-    Auth(GCPAuthError),
-    Transport(tonic::transport::Error),
-    Status(tonic::Status),
+/// Why the acknowledgement of a single message failed.
+///
+/// Acknowledgements only fail per message on subscriptions with exactly-once delivery enabled.
+/// Transient failures have already been retried when one of these is returned.
+#[derive(Debug, Clone)]
+pub enum AckError {
+    /// The ack id is unknown or has expired, so the message will be delivered again.
+    InvalidAckId,
+    PermissionDenied,
+    /// The subscription is not in a state in which messages can be acknowledged.
+    FailedPrecondition,
+    /// Any other failure reported for the ack id, with the reason given by the server.
+    Other(String),
+    /// The request failed as a whole.
+    Request(Error),
 }
 
-impl std::fmt::Display for Error {
+impl std::fmt::Display for AckError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Error::Auth(e) => e.fmt(f),
-            Error::Transport(e) => e.fmt(f),
-            Error::Status(e) => e.fmt(f),
+            AckError::InvalidAckId => write!(f, "The ack id is invalid or has expired"),
+            AckError::PermissionDenied => write!(f, "Permission to acknowledge was denied"),
+            AckError::FailedPrecondition => {
+                write!(f, "The subscription cannot acknowledge messages now")
+            }
+            AckError::Other(reason) => write!(f, "The acknowledgement failed: {}", reason),
+            AckError::Request(e) => e.fmt(f),
         }
     }
 }
 
-impl std::error::Error for Error {
+impl std::error::Error for AckError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
-    }
-}
-
-impl From<GCPAuthError> for Error {
-    fn from(err: GCPAuthError) -> Self {
-        Error::Auth(err)
-    }
-}
-
-impl From<tonic::transport::Error> for Error {
-    fn from(err: tonic::transport::Error) -> Self {
-        Error::Transport(err)
+        match self {
+            AckError::Request(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
-impl From<tonic::Status> for Error {
-    fn from(err: tonic::Status) -> Self {
-        Error::Status(err)
+impl From<Error> for AckError {
+    fn from(err: Error) -> Self {
+        AckError::Request(err)
     }
 }
@@ -1,27 +1,16 @@
-use std::collections::HashMap;
+mod message;
+mod received_message;
+mod schema;
+mod snapshot;
+mod subscription;
+mod topic;
 
-use crate::proto::google::pubsub::v1::PubsubMessage;
-
-pub struct Message {
-    data: Vec<u8>,
-    attributes: HashMap<String, String>,
-}
-
-impl From<&str> for Message {
-    fn from(s: &str) -> Self {
-        Message {
-            data: s.to_string().into_bytes(),
-            attributes: HashMap::new(),
-        }
-    }
-}
-
-impl Message {
-    pub(crate) fn to_tonic(self) -> PubsubMessage {
-        PubsubMessage {
-            data: self.data,
-            attributes: self.attributes,
-            ..Default::default()
-        }
-    }
-}
+pub use message::{Message, MessageBuilder};
+pub use received_message::ReceivedMessage;
+pub use schema::{Encoding, Schema, SchemaSettings, SchemaType};
+pub use snapshot::{SeekTarget, Snapshot};
+pub use subscription::{
+    DeadLetterPolicy, ExpirationPolicy, OidcToken, PushConfig, RetryPolicy, Subscription,
+    SubscriptionUpdate,
+};
+pub use topic::Topic;
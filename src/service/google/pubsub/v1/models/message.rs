@@ -0,0 +1,175 @@
+use super::super::error::Error;
+use crate::proto::google::pubsub::v1::PubsubMessage;
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    data: Vec<u8>,
+    attributes: HashMap<String, String>,
+    ordering_key: String,
+}
+
+impl From<&str> for Message {
+    fn from(s: &str) -> Self {
+        Message::builder().data(s).build()
+    }
+}
+
+impl From<String> for Message {
+    fn from(s: String) -> Self {
+        Message::builder().data(s).build()
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(data: Vec<u8>) -> Self {
+        Message::builder().data(data).build()
+    }
+}
+
+impl From<Bytes> for Message {
+    fn from(data: Bytes) -> Self {
+        Message::builder().data(data.to_vec()).build()
+    }
+}
+
+impl Message {
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+
+    /// Creates a message whose data is `value` encoded as JSON.
+    pub fn json<T: Serialize>(value: &T) -> Result<Message, Error> {
+        Ok(Message::builder().json(value)?.build())
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn attributes(&self) -> &HashMap<String, String> {
+        &self.attributes
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(|value| value.as_str())
+    }
+
+    pub fn ordering_key(&self) -> &str {
+        &self.ordering_key
+    }
+
+    /// Decodes the data of the message as JSON.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.data)?)
+    }
+
+    pub(crate) fn from_tonic(message: PubsubMessage) -> Self {
+        Message {
+            data: message.data,
+            attributes: message.attributes,
+            ordering_key: message.ordering_key,
+        }
+    }
+
+    pub(crate) fn into_tonic(self) -> PubsubMessage {
+        PubsubMessage {
+            data: self.data,
+            attributes: self.attributes,
+            ordering_key: self.ordering_key,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MessageBuilder {
+    message: Message,
+}
+
+impl MessageBuilder {
+    pub fn data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.message.data = data.into();
+        self
+    }
+
+    /// Sets the data of the message to `value` encoded as JSON.
+    pub fn json<T: Serialize>(mut self, value: &T) -> Result<Self, Error> {
+        self.message.data = serde_json::to_vec(value)?;
+        Ok(self)
+    }
+
+    pub fn attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.message.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn attributes<K, V>(mut self, attributes: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.message.attributes.extend(
+            attributes
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Messages that share an ordering key are delivered in the order they were published.
+    pub fn ordering_key(mut self, ordering_key: impl Into<String>) -> Self {
+        self.message.ordering_key = ordering_key.into();
+        self
+    }
+
+    pub fn build(self) -> Message {
+        self.message
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::Message;
+    use crate::service::google::pubsub::v1::error::Error;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Event {
+        id: i64,
+        name: String,
+    }
+
+    #[test]
+    fn test_builder() {
+        let message = Message::builder()
+            .data("hoge")
+            .attribute("a", "1")
+            .attributes(vec![("b", "2"), ("c", "3")])
+            .ordering_key("key")
+            .build();
+        assert_eq!(b"hoge", message.data());
+        assert_eq!(3, message.attributes().len());
+        assert_eq!(Some("2"), message.attribute("b"));
+        assert_eq!(None, message.attribute("d"));
+        assert_eq!("key", message.ordering_key());
+    }
+
+    #[test]
+    fn test_json() {
+        let event = Event {
+            id: 1,
+            name: "fuga".into(),
+        };
+        let message = Message::json(&event).unwrap();
+        assert_eq!(br#"{"id":1,"name":"fuga"}"#.to_vec(), message.data());
+        assert_eq!(event, message.decode().unwrap());
+
+        let message = Message::from("piyo");
+        assert!(matches!(message.decode::<Event>(), Err(Error::Json(_))));
+    }
+}
@@ -0,0 +1,144 @@
+use super::{
+    super::{
+        error::{AckError, Error},
+        subscriber::{confirm, DeliveryAttempts},
+    },
+    message::Message,
+};
+use crate::{
+    proto::google::pubsub::v1 as pubsub, recorder::OutstandingMessage,
+    util::time::from_proto_timestamp, Client,
+};
+use serde::de::DeserializeOwned;
+use std::{future::Future, sync::Arc, time::SystemTime};
+
+/// A message delivered from a subscription, which has to be acknowledged with its ack id.
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage {
+    subscription: String,
+    ack_id: String,
+    message_id: String,
+    publish_time: Option<SystemTime>,
+    delivery_attempt: Option<i32>,
+    message: Message,
+    /// Counts the message as outstanding on a subscriber until it is acknowledged or nacked.
+    outstanding: Option<Arc<OutstandingMessage>>,
+    /// The delivery attempts counted by a subscriber, which forget the message once it is
+    /// acknowledged.
+    attempts: Option<Arc<DeliveryAttempts>>,
+    /// The client of the subscriber the message was received from, which it is acknowledged with.
+    client: Option<Client>,
+}
+
+/// Messages are equal when their delivery is, whichever subscriber they were received by.
+impl PartialEq for ReceivedMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.subscription == other.subscription
+            && self.ack_id == other.ack_id
+            && self.message_id == other.message_id
+            && self.publish_time == other.publish_time
+            && self.delivery_attempt == other.delivery_attempt
+            && self.message == other.message
+    }
+}
+
+impl ReceivedMessage {
+    pub fn ack_id(&self) -> &str {
+        &self.ack_id
+    }
+
+    pub fn message_id(&self) -> &str {
+        &self.message_id
+    }
+
+    pub fn publish_time(&self) -> Option<SystemTime> {
+        self.publish_time
+    }
+
+    /// How many times the server has attempted to deliver the message, which is only set when
+    /// the subscription has a dead-letter policy.
+    pub fn delivery_attempt(&self) -> Option<i32> {
+        self.delivery_attempt
+    }
+
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    pub fn into_message(self) -> Message {
+        self.message
+    }
+
+    /// Decodes the data of the message as JSON.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        self.message.decode()
+    }
+
+    /// Acknowledges the message and returns a future that resolves once the server has confirmed it.
+    ///
+    /// On a subscription with exactly-once delivery, a successful result guarantees that the
+    /// message will not be delivered again.
+    pub fn ack(&self) -> impl Future<Output = Result<(), AckError>> {
+        self.confirm(None)
+    }
+
+    /// Makes the message immediately available for redelivery.
+    pub fn nack(&self) -> impl Future<Output = Result<(), AckError>> {
+        self.confirm(Some(0))
+    }
+
+    /// Extends the ack deadline of the message to `seconds` from now.
+    pub fn modify_ack_deadline(&self, seconds: i32) -> impl Future<Output = Result<(), AckError>> {
+        self.confirm(Some(seconds))
+    }
+
+    fn confirm(&self, seconds: Option<i32>) -> impl Future<Output = Result<(), AckError>> {
+        if let (Some(outstanding), true) = (&self.outstanding, matches!(seconds, None | Some(0))) {
+            outstanding.release();
+        }
+        if let (Some(attempts), None) = (&self.attempts, seconds) {
+            attempts.forget(&self.message_id);
+        }
+        let subscription = self.subscription.clone();
+        let ack_id = self.ack_id.clone();
+        let client = self.client.clone();
+        async move {
+            let ack_ids = vec![ack_id.clone()];
+            let mut results = confirm(client.as_ref(), subscription, ack_ids, seconds).await;
+            results.remove(&ack_id).unwrap_or(Ok(()))
+        }
+    }
+
+    pub(crate) fn from_tonic(subscription: String, received: pubsub::ReceivedMessage) -> Self {
+        let message = received.message.unwrap_or_default();
+        ReceivedMessage {
+            subscription,
+            ack_id: received.ack_id,
+            message_id: message.message_id.clone(),
+            publish_time: message.publish_time.clone().map(from_proto_timestamp),
+            delivery_attempt: match received.delivery_attempt {
+                0 => None,
+                attempt => Some(attempt),
+            },
+            message: Message::from_tonic(message),
+            outstanding: None,
+            attempts: None,
+            client: None,
+        }
+    }
+
+    pub(crate) fn tracked(mut self, outstanding: OutstandingMessage) -> Self {
+        self.outstanding = Some(Arc::new(outstanding));
+        self
+    }
+
+    pub(crate) fn received_with(mut self, client: Option<Client>) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub(crate) fn counted(mut self, attempts: Arc<DeliveryAttempts>) -> Self {
+        self.attempts = Some(attempts);
+        self
+    }
+}
@@ -0,0 +1,113 @@
+use super::super::schema_name;
+use crate::proto::google::pubsub::v1 as pubsub;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaType {
+    ProtocolBuffer,
+    Avro,
+}
+
+/// How messages validated against a schema are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Binary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    /// The schema id, or its full resource name as returned by the server.
+    pub name: String,
+    pub schema_type: SchemaType,
+    /// The Avro schema in JSON, or the Protocol Buffer definition of a single message.
+    pub definition: String,
+}
+
+/// The schema that messages published on a topic are validated against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaSettings {
+    /// The schema id, or its full resource name as returned by the server.
+    pub schema: String,
+    pub encoding: Encoding,
+}
+
+impl Schema {
+    pub fn avro(name: impl Into<String>, definition: impl Into<String>) -> Schema {
+        Schema {
+            name: name.into(),
+            schema_type: SchemaType::Avro,
+            definition: definition.into(),
+        }
+    }
+
+    pub fn protocol_buffer(name: impl Into<String>, definition: impl Into<String>) -> Schema {
+        Schema {
+            name: name.into(),
+            schema_type: SchemaType::ProtocolBuffer,
+            definition: definition.into(),
+        }
+    }
+
+    pub(crate) fn from_tonic(schema: pubsub::Schema) -> Self {
+        Schema {
+            name: schema.name,
+            schema_type: match pubsub::schema::Type::from_i32(schema.r#type) {
+                Some(pubsub::schema::Type::Avro) => SchemaType::Avro,
+                _ => SchemaType::ProtocolBuffer,
+            },
+            definition: schema.definition,
+        }
+    }
+
+    pub(crate) fn into_tonic(self) -> pubsub::Schema {
+        let schema_type = match self.schema_type {
+            SchemaType::ProtocolBuffer => pubsub::schema::Type::ProtocolBuffer,
+            SchemaType::Avro => pubsub::schema::Type::Avro,
+        };
+        pubsub::Schema {
+            name: schema_name(self.name),
+            r#type: schema_type as i32,
+            definition: self.definition,
+        }
+    }
+}
+
+impl Encoding {
+    pub(crate) fn from_tonic(encoding: i32) -> Self {
+        match pubsub::Encoding::from_i32(encoding) {
+            Some(pubsub::Encoding::Binary) => Encoding::Binary,
+            _ => Encoding::Json,
+        }
+    }
+
+    pub(crate) fn into_tonic(self) -> i32 {
+        let encoding = match self {
+            Encoding::Json => pubsub::Encoding::Json,
+            Encoding::Binary => pubsub::Encoding::Binary,
+        };
+        encoding as i32
+    }
+}
+
+impl SchemaSettings {
+    pub fn new(schema: impl Into<String>, encoding: Encoding) -> SchemaSettings {
+        SchemaSettings {
+            schema: schema.into(),
+            encoding,
+        }
+    }
+
+    pub(crate) fn from_tonic(settings: pubsub::SchemaSettings) -> Self {
+        SchemaSettings {
+            schema: settings.schema,
+            encoding: Encoding::from_tonic(settings.encoding),
+        }
+    }
+
+    pub(crate) fn into_tonic(self) -> pubsub::SchemaSettings {
+        pubsub::SchemaSettings {
+            schema: schema_name(self.schema),
+            encoding: self.encoding.into_tonic(),
+        }
+    }
+}
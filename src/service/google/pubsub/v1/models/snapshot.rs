@@ -0,0 +1,35 @@
+use crate::{proto::google::pubsub::v1 as pubsub, util::time::from_proto_timestamp};
+use std::{collections::HashMap, time::SystemTime};
+
+/// A point-in-time record of the acknowledgement state of a subscription.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The snapshot id, or its full resource name as returned by the server.
+    pub name: String,
+    /// The topic of the subscription the snapshot was created from.
+    pub topic: String,
+    /// When the snapshot is deleted, at the latest seven days after its creation.
+    pub expire_time: Option<SystemTime>,
+    pub labels: HashMap<String, String>,
+}
+
+impl Snapshot {
+    pub(crate) fn from_tonic(snapshot: pubsub::Snapshot) -> Self {
+        Snapshot {
+            name: snapshot.name,
+            topic: snapshot.topic,
+            expire_time: snapshot.expire_time.map(from_proto_timestamp),
+            labels: snapshot.labels,
+        }
+    }
+}
+
+/// Where `seek` moves the acknowledgement state of a subscription to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SeekTarget {
+    /// Messages published before the time are marked acknowledged, and later ones unacknowledged.
+    /// Retained acknowledged messages are redelivered only if `retain_acked_messages` is enabled.
+    Time(SystemTime),
+    /// The acknowledgement state captured by a snapshot of the same topic.
+    Snapshot(String),
+}
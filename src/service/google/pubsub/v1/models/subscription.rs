@@ -0,0 +1,332 @@
+use super::super::{subscription_name, topic_name};
+use crate::{
+    proto::google::pubsub::v1::{self as pubsub, push_config::AuthenticationMethod},
+    util::time::{from_proto_duration, to_proto_duration},
+};
+use std::{collections::HashMap, time::Duration};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subscription {
+    /// The subscription id, or its full resource name as returned by the server.
+    pub name: String,
+    /// The topic id, or its full resource name as returned by the server.
+    pub topic: String,
+    /// Delivers messages to an endpoint instead of waiting for them to be pulled.
+    pub push_config: Option<PushConfig>,
+    /// How long the server waits for an acknowledgement before redelivering a message.
+    /// The server default of 10 seconds is used when this is `None`.
+    pub ack_deadline: Option<Duration>,
+    pub retain_acked_messages: bool,
+    pub message_retention_duration: Option<Duration>,
+    pub labels: HashMap<String, String>,
+    pub enable_message_ordering: bool,
+    /// When the subscription is deleted for inactivity.
+    /// The server default of 31 days is used when this is `None`.
+    pub expiration_policy: Option<ExpirationPolicy>,
+    /// Only messages whose attributes match this expression are delivered.
+    pub filter: String,
+    pub dead_letter_policy: Option<DeadLetterPolicy>,
+    pub retry_policy: Option<RetryPolicy>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PushConfig {
+    pub push_endpoint: String,
+    pub attributes: HashMap<String, String>,
+    /// Attaches an OIDC token for this service account to every push request.
+    pub oidc_token: Option<OidcToken>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OidcToken {
+    pub service_account_email: String,
+    pub audience: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExpirationPolicy {
+    /// The subscription never expires when this is `None`.
+    pub ttl: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeadLetterPolicy {
+    /// The topic id, or its full resource name as returned by the server.
+    pub dead_letter_topic: String,
+    pub max_delivery_attempts: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetryPolicy {
+    pub minimum_backoff: Option<Duration>,
+    pub maximum_backoff: Option<Duration>,
+}
+
+/// The fields to change with `update_subscription`. Fields left as `None` are not modified.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriptionUpdate {
+    /// `PushConfig::default()` turns the subscription into a pull subscription.
+    pub push_config: Option<PushConfig>,
+    pub ack_deadline: Option<Duration>,
+    pub retain_acked_messages: Option<bool>,
+    pub message_retention_duration: Option<Duration>,
+    pub labels: Option<HashMap<String, String>>,
+    pub expiration_policy: Option<ExpirationPolicy>,
+    pub filter: Option<String>,
+    /// `DeadLetterPolicy::default()` removes the dead-letter policy.
+    pub dead_letter_policy: Option<DeadLetterPolicy>,
+    /// `RetryPolicy::default()` removes the retry policy.
+    pub retry_policy: Option<RetryPolicy>,
+}
+
+impl Subscription {
+    pub fn new(name: impl Into<String>, topic: impl Into<String>) -> Subscription {
+        Subscription {
+            name: name.into(),
+            topic: topic.into(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn from_tonic(subscription: pubsub::Subscription) -> Self {
+        Subscription {
+            name: subscription.name,
+            topic: subscription.topic,
+            push_config: subscription
+                .push_config
+                .filter(|config| !config.push_endpoint.is_empty())
+                .map(PushConfig::from_tonic),
+            ack_deadline: match subscription.ack_deadline_seconds {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds as u64)),
+            },
+            retain_acked_messages: subscription.retain_acked_messages,
+            message_retention_duration: subscription
+                .message_retention_duration
+                .map(from_proto_duration),
+            labels: subscription.labels,
+            enable_message_ordering: subscription.enable_message_ordering,
+            expiration_policy: subscription
+                .expiration_policy
+                .map(ExpirationPolicy::from_tonic),
+            filter: subscription.filter,
+            dead_letter_policy: subscription
+                .dead_letter_policy
+                .map(DeadLetterPolicy::from_tonic),
+            retry_policy: subscription.retry_policy.map(RetryPolicy::from_tonic),
+        }
+    }
+
+    pub(crate) fn into_tonic(self) -> pubsub::Subscription {
+        pubsub::Subscription {
+            name: subscription_name(self.name),
+            topic: topic_name(self.topic),
+            push_config: self.push_config.map(PushConfig::into_tonic),
+            ack_deadline_seconds: self
+                .ack_deadline
+                .map(|deadline| deadline.as_secs() as i32)
+                .unwrap_or(0),
+            retain_acked_messages: self.retain_acked_messages,
+            message_retention_duration: self.message_retention_duration.map(to_proto_duration),
+            labels: self.labels,
+            enable_message_ordering: self.enable_message_ordering,
+            expiration_policy: self.expiration_policy.map(ExpirationPolicy::into_tonic),
+            filter: self.filter,
+            dead_letter_policy: self
+                .dead_letter_policy
+                .and_then(DeadLetterPolicy::into_tonic),
+            retry_policy: self.retry_policy.and_then(RetryPolicy::into_tonic),
+            ..Default::default()
+        }
+    }
+}
+
+impl PushConfig {
+    pub fn new(push_endpoint: impl Into<String>) -> PushConfig {
+        PushConfig {
+            push_endpoint: push_endpoint.into(),
+            ..Default::default()
+        }
+    }
+
+    fn from_tonic(config: pubsub::PushConfig) -> Self {
+        PushConfig {
+            push_endpoint: config.push_endpoint,
+            attributes: config.attributes,
+            oidc_token: config.authentication_method.map(|method| match method {
+                AuthenticationMethod::OidcToken(token) => OidcToken {
+                    service_account_email: token.service_account_email,
+                    audience: token.audience,
+                },
+            }),
+        }
+    }
+
+    pub(crate) fn into_tonic(self) -> pubsub::PushConfig {
+        pubsub::PushConfig {
+            push_endpoint: self.push_endpoint,
+            attributes: self.attributes,
+            authentication_method: self.oidc_token.map(|token| {
+                AuthenticationMethod::OidcToken(pubsub::push_config::OidcToken {
+                    service_account_email: token.service_account_email,
+                    audience: token.audience,
+                })
+            }),
+        }
+    }
+}
+
+impl ExpirationPolicy {
+    fn from_tonic(policy: pubsub::ExpirationPolicy) -> Self {
+        ExpirationPolicy {
+            ttl: policy.ttl.map(from_proto_duration),
+        }
+    }
+
+    fn into_tonic(self) -> pubsub::ExpirationPolicy {
+        pubsub::ExpirationPolicy {
+            ttl: self.ttl.map(to_proto_duration),
+        }
+    }
+}
+
+impl DeadLetterPolicy {
+    pub fn new(dead_letter_topic: impl Into<String>, max_delivery_attempts: i32) -> Self {
+        DeadLetterPolicy {
+            dead_letter_topic: dead_letter_topic.into(),
+            max_delivery_attempts,
+        }
+    }
+
+    fn from_tonic(policy: pubsub::DeadLetterPolicy) -> Self {
+        DeadLetterPolicy {
+            dead_letter_topic: policy.dead_letter_topic,
+            max_delivery_attempts: policy.max_delivery_attempts,
+        }
+    }
+
+    fn into_tonic(self) -> Option<pubsub::DeadLetterPolicy> {
+        if self == DeadLetterPolicy::default() {
+            return None;
+        }
+        Some(pubsub::DeadLetterPolicy {
+            dead_letter_topic: topic_name(self.dead_letter_topic),
+            max_delivery_attempts: self.max_delivery_attempts,
+        })
+    }
+}
+
+impl RetryPolicy {
+    fn from_tonic(policy: pubsub::RetryPolicy) -> Self {
+        RetryPolicy {
+            minimum_backoff: policy.minimum_backoff.map(from_proto_duration),
+            maximum_backoff: policy.maximum_backoff.map(from_proto_duration),
+        }
+    }
+
+    fn into_tonic(self) -> Option<pubsub::RetryPolicy> {
+        if self == RetryPolicy::default() {
+            return None;
+        }
+        Some(pubsub::RetryPolicy {
+            minimum_backoff: self.minimum_backoff.map(to_proto_duration),
+            maximum_backoff: self.maximum_backoff.map(to_proto_duration),
+        })
+    }
+}
+
+impl SubscriptionUpdate {
+    /// Returns the subscription carrying the new values together with the paths of the fields to update.
+    pub(crate) fn into_tonic(self, name: String) -> (pubsub::Subscription, Vec<String>) {
+        let mut paths = Vec::new();
+        let mut subscription = pubsub::Subscription {
+            name,
+            ..Default::default()
+        };
+        if let Some(push_config) = self.push_config {
+            subscription.push_config = Some(push_config.into_tonic());
+            paths.push("push_config");
+        }
+        if let Some(ack_deadline) = self.ack_deadline {
+            subscription.ack_deadline_seconds = ack_deadline.as_secs() as i32;
+            paths.push("ack_deadline_seconds");
+        }
+        if let Some(retain_acked_messages) = self.retain_acked_messages {
+            subscription.retain_acked_messages = retain_acked_messages;
+            paths.push("retain_acked_messages");
+        }
+        if let Some(duration) = self.message_retention_duration {
+            subscription.message_retention_duration = Some(to_proto_duration(duration));
+            paths.push("message_retention_duration");
+        }
+        if let Some(labels) = self.labels {
+            subscription.labels = labels;
+            paths.push("labels");
+        }
+        if let Some(expiration_policy) = self.expiration_policy {
+            subscription.expiration_policy = Some(expiration_policy.into_tonic());
+            paths.push("expiration_policy");
+        }
+        if let Some(filter) = self.filter {
+            subscription.filter = filter;
+            paths.push("filter");
+        }
+        if let Some(dead_letter_policy) = self.dead_letter_policy {
+            subscription.dead_letter_policy = dead_letter_policy.into_tonic();
+            paths.push("dead_letter_policy");
+        }
+        if let Some(retry_policy) = self.retry_policy {
+            subscription.retry_policy = retry_policy.into_tonic();
+            paths.push("retry_policy");
+        }
+        (subscription, paths.into_iter().map(String::from).collect())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{PushConfig, RetryPolicy, Subscription, SubscriptionUpdate};
+    use crate::proto::google::pubsub::v1 as pubsub;
+    use std::time::Duration;
+
+    #[test]
+    fn test_from_tonic() {
+        let subscription = Subscription::from_tonic(pubsub::Subscription {
+            name: "projects/p/subscriptions/s".into(),
+            topic: "projects/p/topics/t".into(),
+            ..Default::default()
+        });
+        assert_eq!("projects/p/subscriptions/s", subscription.name);
+        assert_eq!("projects/p/topics/t", subscription.topic);
+    }
+
+    #[test]
+    fn test_update_mask() {
+        let update = SubscriptionUpdate {
+            push_config: Some(PushConfig::new("https://example.com/push")),
+            ack_deadline: Some(Duration::from_secs(30)),
+            filter: Some("attributes:hoge".into()),
+            retry_policy: Some(RetryPolicy::default()),
+            ..Default::default()
+        };
+        let (subscription, paths) = update.into_tonic("projects/p/subscriptions/s".into());
+        assert_eq!(
+            vec![
+                "push_config",
+                "ack_deadline_seconds",
+                "filter",
+                "retry_policy"
+            ],
+            paths
+        );
+        assert_eq!("projects/p/subscriptions/s", subscription.name);
+        assert_eq!(30, subscription.ack_deadline_seconds);
+        assert_eq!(
+            "https://example.com/push",
+            subscription.push_config.unwrap().push_endpoint
+        );
+        assert!(subscription.retry_policy.is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use super::{super::topic_name, SchemaSettings};
+use crate::proto::google::pubsub::v1 as pubsub;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Topic {
+    /// The topic id, or its full resource name as returned by the server.
+    pub name: String,
+    pub labels: HashMap<String, String>,
+    /// The Cloud KMS key used to protect access to messages published on this topic.
+    pub kms_key_name: String,
+    /// Messages published on the topic are rejected unless they match this schema.
+    pub schema_settings: Option<SchemaSettings>,
+}
+
+impl Topic {
+    pub fn new(name: impl Into<String>) -> Topic {
+        Topic {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn from_tonic(topic: pubsub::Topic) -> Self {
+        Topic {
+            name: topic.name,
+            labels: topic.labels,
+            kms_key_name: topic.kms_key_name,
+            schema_settings: topic.schema_settings.map(SchemaSettings::from_tonic),
+        }
+    }
+
+    pub(crate) fn into_tonic(self) -> pubsub::Topic {
+        pubsub::Topic {
+            name: topic_name(self.name),
+            labels: self.labels,
+            kms_key_name: self.kms_key_name,
+            schema_settings: self.schema_settings.map(SchemaSettings::into_tonic),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&str> for Topic {
+    fn from(name: &str) -> Self {
+        Topic::new(name)
+    }
+}
+
+impl From<String> for Topic {
+    fn from(name: String) -> Self {
+        Topic::new(name)
+    }
+}
@@ -0,0 +1,898 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A Protocol Buffer schema parsed from its `.proto` definition.
+///
+/// Payloads are checked against the first top-level message of the definition in the proto3 JSON
+/// mapping, which is the JSON encoding Pub/Sub accepts for Protocol Buffer schemas, and can be
+/// converted into the binary encoding of that message.
+#[derive(Debug, Clone)]
+pub(crate) struct ProtoSchema {
+    root: String,
+    messages: HashMap<String, MessageType>,
+    /// The symbols of each enum, with their numbers.
+    enums: HashMap<String, Vec<(String, i32)>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MessageType {
+    fields: Vec<Field>,
+    /// The number of `oneof` declarations, which fields refer to by index.
+    oneofs: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    json_name: String,
+    number: u32,
+    label: Label,
+    ty: Type,
+    oneof: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Label {
+    Single,
+    Repeated,
+    Map(Type),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Double,
+    Float,
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    Sfixed32,
+    Sfixed64,
+    Bool,
+    String,
+    Bytes,
+    Message(String),
+    Enum(String),
+    /// A message or enum name as written in the definition, before it is resolved.
+    Named {
+        name: String,
+        scope: String,
+    },
+}
+
+impl ProtoSchema {
+    pub(crate) fn parse(definition: &str) -> Result<ProtoSchema, String> {
+        let mut parser = Parser {
+            tokens: tokenize(definition)?,
+            position: 0,
+            package: String::new(),
+            messages: HashMap::new(),
+            enums: HashMap::new(),
+            root: None,
+        };
+        parser.parse_file()?;
+        let root = parser
+            .root
+            .clone()
+            .ok_or("The Protocol Buffer schema defines no message")?;
+        let mut schema = ProtoSchema {
+            root,
+            messages: HashMap::new(),
+            enums: parser.enums,
+        };
+        let messages = parser.messages;
+        for (name, mut message) in messages.clone() {
+            for field in &mut message.fields {
+                field.ty = resolve(&field.ty, &messages, &schema.enums)?;
+            }
+            schema.messages.insert(name, message);
+        }
+        Ok(schema)
+    }
+
+    /// Checks `value` against the top-level message of the schema.
+    pub(crate) fn check(&self, value: &Value) -> Result<(), String> {
+        self.check_message(&self.root, value, "$")
+    }
+
+    /// Checks `value` against the top-level message of the schema and returns it in the binary
+    /// encoding.
+    pub(crate) fn to_binary(&self, value: &Value) -> Result<Vec<u8>, String> {
+        self.check(value)?;
+        let mut out = Vec::new();
+        self.write_message(&self.root, value, &mut out);
+        Ok(out)
+    }
+
+    fn check_message(&self, name: &str, value: &Value, path: &str) -> Result<(), String> {
+        let entries = match value {
+            Value::Object(entries) => entries,
+            _ => return Err(format!("`{}`: expected {}, found {}", path, name, value)),
+        };
+        let message = &self.messages[name];
+        let mut oneofs = vec![None; message.oneofs];
+        for (key, value) in entries {
+            let field = message
+                .fields
+                .iter()
+                .find(|field| &field.json_name == key || &field.name == key)
+                .ok_or_else(|| format!("`{}`: unknown field {:?}", path, key))?;
+            if value.is_null() {
+                continue;
+            }
+            let path = format!("{}.{}", path, key);
+            if let Some(index) = field.oneof {
+                if let Some(other) = oneofs[index].replace(key) {
+                    return Err(format!(
+                        "`{}`: only one of {:?} and {:?} can be set",
+                        path, other, key
+                    ));
+                }
+            }
+            match (&field.label, value) {
+                (Label::Single, _) => self.check_value(&field.ty, value, &path)?,
+                (Label::Repeated, Value::Array(values)) => {
+                    for (i, value) in values.iter().enumerate() {
+                        self.check_value(&field.ty, value, &format!("{}[{}]", path, i))?;
+                    }
+                }
+                (Label::Map(key_type), Value::Object(entries)) => {
+                    for (key, value) in entries {
+                        let path = format!("{}.{}", path, key);
+                        if !is_key(key_type, key) {
+                            return Err(format!("`{}`: invalid map key {:?}", path, key));
+                        }
+                        self.check_value(&field.ty, value, &path)?;
+                    }
+                }
+                (Label::Repeated, _) => {
+                    return Err(format!("`{}`: expected an array, found {}", path, value))
+                }
+                (Label::Map(_), _) => {
+                    return Err(format!("`{}`: expected a map, found {}", path, value))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_value(&self, ty: &Type, value: &Value, path: &str) -> Result<(), String> {
+        let valid = match (ty, value) {
+            (Type::Message(name), _) => return self.check_message(name, value, path),
+            (Type::Enum(name), Value::String(symbol)) => self.enum_number(name, symbol).is_some(),
+            (Type::Enum(_), Value::Number(n)) => {
+                matches!(n.as_i64(), Some(n) if i32::MIN as i64 <= n && n <= i32::MAX as i64)
+            }
+            (Type::Bool, Value::Bool(_)) | (Type::String, Value::String(_)) => true,
+            (Type::Bytes, Value::String(s)) => bytes(s).is_some(),
+            (Type::Double, _) | (Type::Float, _) => float(value).is_some(),
+            (Type::String, _) | (Type::Bytes, _) | (Type::Bool, _) | (Type::Enum(_), _) => false,
+            (Type::Named { .. }, _) => common_panic!(),
+            _ => matches!(integer(value), Some(n) if in_range(ty, n)),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!(
+                "`{}`: expected {}, found {}",
+                path,
+                type_name(ty),
+                value
+            ))
+        }
+    }
+}
+
+impl ProtoSchema {
+    fn enum_number(&self, name: &str, symbol: &str) -> Option<i32> {
+        let values = self.enums[name].iter();
+        values
+            .map(|(value, number)| (value, *number))
+            .find(|(value, _)| value.as_str() == symbol)
+            .map(|(_, number)| number)
+    }
+
+    /// Writes a message that has been checked by `check_message` in the binary encoding.
+    fn write_message(&self, name: &str, value: &Value, out: &mut Vec<u8>) {
+        let entries = match value {
+            Value::Object(entries) => entries,
+            _ => common_panic!(),
+        };
+        for field in &self.messages[name].fields {
+            let value = entries
+                .get(&field.json_name)
+                .or_else(|| entries.get(&field.name));
+            let value = match value {
+                Some(value) if !value.is_null() => value,
+                _ => continue,
+            };
+            match (&field.label, value) {
+                (Label::Single, _) => self.write_field(field.number, &field.ty, value, out),
+                (Label::Repeated, Value::Array(values)) if wire_type(&field.ty) != LENGTH => {
+                    let mut packed = Vec::new();
+                    for value in values {
+                        self.write_value(&field.ty, value, &mut packed);
+                    }
+                    write_tag(field.number, LENGTH, out);
+                    write_varint(packed.len() as u64, out);
+                    out.extend(packed);
+                }
+                (Label::Repeated, Value::Array(values)) => {
+                    for value in values {
+                        self.write_field(field.number, &field.ty, value, out);
+                    }
+                }
+                (Label::Map(key_type), Value::Object(entries)) => {
+                    for (key, value) in entries {
+                        let key = match key_type {
+                            Type::Bool => Value::Bool(key == "true"),
+                            _ => Value::String(key.clone()),
+                        };
+                        let mut entry = Vec::new();
+                        self.write_field(1, key_type, &key, &mut entry);
+                        self.write_field(2, &field.ty, value, &mut entry);
+                        write_tag(field.number, LENGTH, out);
+                        write_varint(entry.len() as u64, out);
+                        out.extend(entry);
+                    }
+                }
+                _ => common_panic!(),
+            }
+        }
+    }
+
+    fn write_field(&self, number: u32, ty: &Type, value: &Value, out: &mut Vec<u8>) {
+        write_tag(number, wire_type(ty), out);
+        self.write_value(ty, value, out);
+    }
+
+    fn write_value(&self, ty: &Type, value: &Value, out: &mut Vec<u8>) {
+        let n = || integer(value).unwrap_or_else(|| common_panic!());
+        match ty {
+            Type::Message(name) => {
+                let mut message = Vec::new();
+                self.write_message(name, value, &mut message);
+                write_varint(message.len() as u64, out);
+                out.extend(message);
+            }
+            Type::Enum(name) => {
+                let number = match value {
+                    Value::String(symbol) => self.enum_number(name, symbol).map(i64::from),
+                    _ => value.as_i64(),
+                };
+                write_varint(number.unwrap_or_else(|| common_panic!()) as u64, out);
+            }
+            Type::Bool => write_varint(
+                value.as_bool().unwrap_or_else(|| common_panic!()) as u64,
+                out,
+            ),
+            Type::String => {
+                let s = value.as_str().unwrap_or_else(|| common_panic!());
+                write_varint(s.len() as u64, out);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Type::Bytes => {
+                let s = value.as_str().unwrap_or_else(|| common_panic!());
+                let bytes = bytes(s).unwrap_or_else(|| common_panic!());
+                write_varint(bytes.len() as u64, out);
+                out.extend(bytes);
+            }
+            Type::Double => {
+                let n = float(value).unwrap_or_else(|| common_panic!());
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            Type::Float => {
+                let n = float(value).unwrap_or_else(|| common_panic!()) as f32;
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            // Negative values of `int32` are sign-extended to 64 bits.
+            Type::Int32 | Type::Int64 | Type::Uint32 | Type::Uint64 => {
+                write_varint(n() as u64, out)
+            }
+            Type::Sint32 | Type::Sint64 => {
+                let n = n() as i64;
+                write_varint(((n << 1) ^ (n >> 63)) as u64, out);
+            }
+            Type::Fixed32 | Type::Sfixed32 => out.extend_from_slice(&(n() as u32).to_le_bytes()),
+            Type::Fixed64 | Type::Sfixed64 => out.extend_from_slice(&(n() as u64).to_le_bytes()),
+            Type::Named { .. } => common_panic!(),
+        }
+    }
+}
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH: u8 = 2;
+const FIXED32: u8 = 5;
+
+fn wire_type(ty: &Type) -> u8 {
+    match ty {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => FIXED64,
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => FIXED32,
+        Type::String | Type::Bytes | Type::Message(_) => LENGTH,
+        _ => VARINT,
+    }
+}
+
+fn write_tag(number: u32, wire_type: u8, out: &mut Vec<u8>) {
+    write_varint(u64::from(number) << 3 | u64::from(wire_type), out);
+}
+
+fn write_varint(mut n: u64, out: &mut Vec<u8>) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn in_range(ty: &Type, n: i128) -> bool {
+    match ty {
+        Type::Int32 | Type::Sint32 | Type::Sfixed32 => {
+            i32::MIN as i128 <= n && n <= i32::MAX as i128
+        }
+        Type::Int64 | Type::Sint64 | Type::Sfixed64 => {
+            i64::MIN as i128 <= n && n <= i64::MAX as i128
+        }
+        Type::Uint32 | Type::Fixed32 => 0 <= n && n <= u32::MAX as i128,
+        _ => 0 <= n && n <= u64::MAX as i128,
+    }
+}
+
+/// An integer, which the proto3 JSON mapping allows as a number or a string.
+fn integer(value: &Value) -> Option<i128> {
+    match value {
+        Value::Number(n) => n.as_i64().map(i128::from).or_else(|| {
+            n.as_u64().map(i128::from).or_else(|| {
+                n.as_f64()
+                    .filter(|n| n.fract() == 0.0 && n.abs() < 1e19)
+                    .map(|n| n as i128)
+            })
+        }),
+        Value::String(s) => s.parse::<i128>().ok(),
+        _ => None,
+    }
+}
+
+/// A floating point number, which may also be written as a string, such as `"NaN"`.
+fn float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => match s.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            s => s.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+fn bytes(s: &str) -> Option<Vec<u8>> {
+    base64::decode(s)
+        .or_else(|_| base64::decode_config(s, base64::URL_SAFE))
+        .ok()
+}
+
+/// Map keys are always strings in JSON, holding the value of the key type.
+fn is_key(ty: &Type, key: &str) -> bool {
+    match ty {
+        Type::String => true,
+        Type::Bool => key == "true" || key == "false",
+        _ => matches!(key.parse::<i128>(), Ok(n) if in_range(ty, n)),
+    }
+}
+
+fn type_name(ty: &Type) -> &str {
+    match ty {
+        Type::Double => "double",
+        Type::Float => "float",
+        Type::Int32 => "int32",
+        Type::Int64 => "int64",
+        Type::Uint32 => "uint32",
+        Type::Uint64 => "uint64",
+        Type::Sint32 => "sint32",
+        Type::Sint64 => "sint64",
+        Type::Fixed32 => "fixed32",
+        Type::Fixed64 => "fixed64",
+        Type::Sfixed32 => "sfixed32",
+        Type::Sfixed64 => "sfixed64",
+        Type::Bool => "bool",
+        Type::String => "string",
+        Type::Bytes => "bytes",
+        Type::Message(name) | Type::Enum(name) | Type::Named { name, .. } => name,
+    }
+}
+
+fn scalar(name: &str) -> Option<Type> {
+    let ty = match name {
+        "double" => Type::Double,
+        "float" => Type::Float,
+        "int32" => Type::Int32,
+        "int64" => Type::Int64,
+        "uint32" => Type::Uint32,
+        "uint64" => Type::Uint64,
+        "sint32" => Type::Sint32,
+        "sint64" => Type::Sint64,
+        "fixed32" => Type::Fixed32,
+        "fixed64" => Type::Fixed64,
+        "sfixed32" => Type::Sfixed32,
+        "sfixed64" => Type::Sfixed64,
+        "bool" => Type::Bool,
+        "string" => Type::String,
+        "bytes" => Type::Bytes,
+        _ => return None,
+    };
+    Some(ty)
+}
+
+/// Resolves a type name by the scoping rules of Protocol Buffers: from the innermost scope of
+/// the field outwards, or from the root for a name that starts with a dot.
+fn resolve(
+    ty: &Type,
+    messages: &HashMap<String, MessageType>,
+    enums: &HashMap<String, Vec<(String, i32)>>,
+) -> Result<Type, String> {
+    let (name, scope) = match ty {
+        Type::Named { name, scope } => (name, scope.as_str()),
+        ty => return Ok(ty.clone()),
+    };
+    let candidates = match name.strip_prefix('.') {
+        Some(name) => vec![name.to_string()],
+        None => {
+            let mut candidates = Vec::new();
+            let mut scope = scope;
+            loop {
+                candidates.push(match scope {
+                    "" => name.clone(),
+                    scope => format!("{}.{}", scope, name),
+                });
+                if scope.is_empty() {
+                    break;
+                }
+                scope = match scope.rfind('.') {
+                    Some(index) => &scope[..index],
+                    None => "",
+                };
+            }
+            candidates
+        }
+    };
+    for candidate in candidates {
+        if messages.contains_key(&candidate) {
+            return Ok(Type::Message(candidate));
+        }
+        if enums.contains_key(&candidate) {
+            return Ok(Type::Enum(candidate));
+        }
+    }
+    Err(format!("Unknown Protocol Buffer type {:?}", name))
+}
+
+/// The JSON name of a field, which is its name in lower camel case.
+fn json_name(name: &str) -> String {
+    let mut json_name = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            json_name.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Symbol(char),
+}
+
+fn tokenize(definition: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = definition.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                while !matches!(chars.next(), Some('\n') | None) {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(c) => previous = c,
+                        None => return Err("Unterminated comment".into()),
+                    }
+                }
+            }
+            '"' | '\'' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\\') => s.extend(chars.next()),
+                        Some(c) => s.push(c),
+                        None => return Err("Unterminated string".into()),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+') => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || matches!(c, '_' | '.')) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => tokens.push(Token::Symbol(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    package: String,
+    messages: HashMap<String, MessageType>,
+    enums: HashMap<String, Vec<(String, i32)>>,
+    root: Option<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or("Unexpected end of the Protocol Buffer schema")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(format!("Expected a name, found {:?}", token)),
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Symbol(c) if c == symbol => Ok(()),
+            token => Err(format!("Expected {:?}, found {:?}", symbol, token)),
+        }
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// Skips a statement up to its `;`, or a block up to its matching `}`.
+    fn skip(&mut self) -> Result<(), String> {
+        let mut depth = 0;
+        loop {
+            match self.next()? {
+                Token::Symbol(';') if depth == 0 => return Ok(()),
+                Token::Symbol('{') => depth += 1,
+                Token::Symbol('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_file(&mut self) -> Result<(), String> {
+        while self.peek().is_some() {
+            if self.eat(';') {
+                continue;
+            }
+            match self.word()?.as_str() {
+                "package" => {
+                    self.package = self.word()?;
+                    self.expect(';')?;
+                }
+                "import" => return Err("Imports are not supported in Pub/Sub schemas".into()),
+                "message" => {
+                    let scope = self.package.clone();
+                    let name = self.parse_message(&scope)?;
+                    self.root.get_or_insert(name);
+                }
+                "enum" => {
+                    let scope = self.package.clone();
+                    self.parse_enum(&scope)?;
+                }
+                // `syntax`, `option`, `service` and `extend` do not affect the JSON encoding.
+                _ => self.skip()?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_message(&mut self, scope: &str) -> Result<String, String> {
+        let name = qualified(scope, &self.word()?);
+        self.expect('{')?;
+        let mut message = MessageType::default();
+        self.parse_fields(&name, &mut message, None)?;
+        self.messages.insert(name.clone(), message);
+        Ok(name)
+    }
+
+    /// Parses the body of a message, or of a `oneof` with the index `oneof`, up to its `}`.
+    fn parse_fields(
+        &mut self,
+        scope: &str,
+        message: &mut MessageType,
+        oneof: Option<usize>,
+    ) -> Result<(), String> {
+        loop {
+            if self.eat('}') {
+                return Ok(());
+            }
+            if self.eat(';') {
+                continue;
+            }
+            let word = self.word()?;
+            match word.as_str() {
+                "message" if oneof.is_none() => {
+                    self.parse_message(scope)?;
+                }
+                "enum" if oneof.is_none() => self.parse_enum(scope)?,
+                "oneof" if oneof.is_none() => {
+                    self.word()?;
+                    self.expect('{')?;
+                    let index = message.oneofs;
+                    message.oneofs += 1;
+                    self.parse_fields(scope, message, Some(index))?;
+                }
+                "option" | "reserved" | "extensions" | "extend" => self.skip()?,
+                "group" => return Err("Groups are not supported".into()),
+                "map" => {
+                    self.expect('<')?;
+                    let key = self.word()?;
+                    let key = scalar(&key)
+                        .filter(|key| !matches!(key, Type::Double | Type::Float | Type::Bytes))
+                        .ok_or_else(|| format!("Invalid map key type {:?}", key))?;
+                    self.expect(',')?;
+                    let value = self.word()?;
+                    self.expect('>')?;
+                    let field = self.parse_field(scope, &value, Label::Map(key), oneof)?;
+                    message.fields.push(field);
+                }
+                "repeated" => {
+                    let ty = self.word()?;
+                    let field = self.parse_field(scope, &ty, Label::Repeated, oneof)?;
+                    message.fields.push(field);
+                }
+                "optional" | "required" => {
+                    let ty = self.word()?;
+                    let field = self.parse_field(scope, &ty, Label::Single, oneof)?;
+                    message.fields.push(field);
+                }
+                _ => {
+                    let field = self.parse_field(scope, &word, Label::Single, oneof)?;
+                    message.fields.push(field);
+                }
+            }
+        }
+    }
+
+    /// Parses the rest of a field declaration after its type: `name = number [options];`
+    fn parse_field(
+        &mut self,
+        scope: &str,
+        ty: &str,
+        label: Label,
+        oneof: Option<usize>,
+    ) -> Result<Field, String> {
+        let name = self.word()?;
+        self.expect('=')?;
+        let number = self.word()?;
+        let number = number
+            .parse::<u32>()
+            .ok()
+            .filter(|&number| 0 < number && number < 1 << 29)
+            .ok_or_else(|| format!("Invalid number {:?} of {}", number, name))?;
+        let mut json_name = json_name(&name);
+        if self.eat('[') {
+            loop {
+                let option = self.word()?;
+                self.expect('=')?;
+                let value = self.next()?;
+                if let ("json_name", Token::Str(value)) = (option.as_str(), value) {
+                    json_name = value;
+                }
+                if self.eat(']') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        self.expect(';')?;
+        let ty = scalar(ty).unwrap_or_else(|| Type::Named {
+            name: ty.to_string(),
+            scope: scope.to_string(),
+        });
+        Ok(Field {
+            name,
+            json_name,
+            number,
+            label,
+            ty,
+            oneof,
+        })
+    }
+
+    fn parse_enum(&mut self, scope: &str) -> Result<(), String> {
+        let name = qualified(scope, &self.word()?);
+        self.expect('{')?;
+        let mut values = Vec::new();
+        loop {
+            if self.eat('}') {
+                break;
+            }
+            if self.eat(';') {
+                continue;
+            }
+            let word = self.word()?;
+            if word == "option" || word == "reserved" {
+                self.skip()?;
+                continue;
+            }
+            self.expect('=')?;
+            let number = self.word()?;
+            let number = number
+                .parse::<i32>()
+                .map_err(|_| format!("Invalid number {:?} of {}", number, word))?;
+            if self.eat('[') {
+                while !self.eat(']') {
+                    self.next()?;
+                }
+            }
+            self.expect(';')?;
+            values.push((word, number));
+        }
+        self.enums.insert(name, values);
+        Ok(())
+    }
+}
+
+fn qualified(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::ProtoSchema;
+    use serde_json::json;
+
+    const USER: &str = r#"
+        syntax = "proto3";
+        package example;
+
+        // A user of the service.
+        message User {
+            enum Role {
+                ROLE_UNSPECIFIED = 0;
+                ADMIN = 1;
+            }
+            message Address {
+                string city = 1;
+            }
+            int64 id = 1;
+            string display_name = 2;
+            repeated Address addresses = 3;
+            map<int32, Role> roles = 4;
+            oneof contact {
+                string email = 5;
+                string phone = 6 [json_name = "tel"];
+            }
+            bytes avatar = 7;
+            optional double score = 8;
+            repeated sint32 deltas = 9;
+        }
+    "#;
+
+    #[test]
+    fn test_check() {
+        let schema = ProtoSchema::parse(USER).unwrap();
+        let value = json!({
+            "id": "12345678901234",
+            "displayName": "a",
+            "addresses": [{"city": "Tokyo"}],
+            "roles": {"1": "ADMIN", "2": 0},
+            "tel": "000",
+            "avatar": "AAEC",
+            "score": "NaN",
+        });
+        assert_eq!(Ok(()), schema.check(&value));
+        assert_eq!(Ok(()), schema.check(&json!({"display_name": null})));
+    }
+
+    #[test]
+    fn test_binary() {
+        let schema = ProtoSchema::parse(USER).unwrap();
+        let value = json!({
+            "id": "150",
+            "displayName": "a",
+            "addresses": [{"city": "T"}],
+            "roles": {"1": "ADMIN"},
+            "tel": "0",
+            "deltas": [-1, 1],
+        });
+        assert_eq!(
+            vec![
+                0x08, 0x96, 0x01, // id
+                0x12, 0x01, b'a', // display_name
+                0x1a, 0x03, 0x0a, 0x01, b'T', // addresses
+                0x22, 0x04, 0x08, 0x01, 0x10, 0x01, // roles
+                0x32, 0x01, b'0', // phone
+                0x4a, 0x02, 0x01, 0x02, // deltas
+            ],
+            schema.to_binary(&value).unwrap()
+        );
+        assert!(schema.to_binary(&json!({"id": "a"})).is_err());
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let schema = ProtoSchema::parse(USER).unwrap();
+        assert_eq!(
+            Err("`$.addresses[0].city`: expected string, found 1".to_string()),
+            schema.check(&json!({"addresses": [{"city": 1}]}))
+        );
+        assert_eq!(
+            Err("`$`: unknown field \"name\"".to_string()),
+            schema.check(&json!({"name": "a"}))
+        );
+        assert_eq!(
+            Err("`$.roles.1`: expected example.User.Role, found \"OWNER\"".to_string()),
+            schema.check(&json!({"roles": {"1": "OWNER"}}))
+        );
+        assert!(schema.check(&json!({"email": "a", "tel": "0"})).is_err());
+        assert!(schema.check(&json!({"roles": {"a": "ADMIN"}})).is_err());
+        assert!(schema.check(&json!({"id": 1.5})).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(ProtoSchema::parse("message A { B b = 1; }").is_err());
+        assert!(ProtoSchema::parse("import \"other.proto\"; message A {}").is_err());
+        assert!(ProtoSchema::parse("syntax = \"proto3\";").is_err());
+        assert!(ProtoSchema::parse("message A { string a = 0; }").is_err());
+    }
+}
@@ -0,0 +1,647 @@
+use super::{
+    error::Error,
+    models::{Message, MessageBuilder},
+    resource_name_in,
+    schema::SchemaEncoder,
+    topic_name,
+};
+use crate::{
+    proto::google::pubsub::v1::{publisher_client::PublisherClient, PublishRequest, PubsubMessage},
+    recorder::Outstanding,
+    retry, CallOptions, Client,
+};
+use futures::{stream::FuturesUnordered, StreamExt};
+use prost::Message as _;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    mem,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+/// Thresholds that decide when the messages buffered by a [`Publisher`] are sent, and the options
+/// of the requests they are sent with.
+///
+/// A batch is sent as soon as any one of the thresholds is reached.
+#[derive(Debug, Clone)]
+pub struct BatchSettings {
+    /// Maximum number of messages in a single `PublishRequest`.
+    pub max_messages: usize,
+    /// Maximum total size, in bytes, of the messages in a single `PublishRequest`.
+    pub max_bytes: usize,
+    /// Maximum time a message waits in the buffer before its batch is sent.
+    pub delay: Duration,
+    /// The options of every `PublishRequest`, such as its deadline.
+    pub call_options: CallOptions,
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        BatchSettings {
+            max_messages: 100,
+            max_bytes: 1_000_000,
+            delay: Duration::from_millis(10),
+            call_options: CallOptions::default(),
+        }
+    }
+}
+
+/// A long-lived publisher for a single topic.
+///
+/// Messages passed to [`Publisher::publish`] are buffered and sent together according to
+/// [`BatchSettings`]. A background task owns the buffer, so a `Publisher` has to be created
+/// inside a Tokio runtime.
+pub struct Publisher {
+    sender: mpsc::UnboundedSender<Command>,
+    handle: JoinHandle<()>,
+    encoder: Option<SchemaEncoder>,
+    outstanding: Arc<Outstanding>,
+}
+
+impl Publisher {
+    pub fn new(topic: impl Into<String>) -> Publisher {
+        Publisher::with_settings(topic, BatchSettings::default())
+    }
+
+    pub fn with_settings(topic: impl Into<String>, settings: BatchSettings) -> Publisher {
+        Publisher::spawn(
+            Destination {
+                topic: topic_name(topic),
+                client: None,
+                options: settings.call_options.clone(),
+            },
+            settings,
+        )
+    }
+
+    /// Creates a publisher for a topic in the project of `client`.
+    pub fn with_client(
+        client: &Client,
+        topic: impl Into<String>,
+        settings: BatchSettings,
+    ) -> Publisher {
+        let topic = resource_name_in(client.project_id(), "topics", topic.into());
+        Publisher::spawn(
+            Destination {
+                topic,
+                client: Some(client.clone()),
+                options: settings.call_options.clone(),
+            },
+            settings,
+        )
+    }
+
+    fn spawn(destination: Destination, settings: BatchSettings) -> Publisher {
+        let recorder = destination
+            .client
+            .as_ref()
+            .and_then(Client::metrics_recorder);
+        let outstanding = Outstanding::new(destination.topic.clone(), recorder);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(run(destination, settings, receiver));
+        Publisher {
+            sender,
+            handle,
+            encoder: None,
+            outstanding,
+        }
+    }
+
+    /// Creates a publisher that encodes the payloads passed to [`Publisher::publish_payload`]
+    /// according to the schema settings of `topic`.
+    ///
+    /// Payloads are validated against the schema before they are buffered, so a payload that does
+    /// not match fails with [`Error::Schema`] instead of failing the whole batch on the server.
+    pub async fn with_schema(
+        topic: impl Into<String>,
+        settings: BatchSettings,
+    ) -> Result<Publisher, Error> {
+        let topic = topic_name(topic);
+        let encoder = SchemaEncoder::for_topic(None, topic.clone()).await?;
+        let mut publisher = Publisher::with_settings(topic, settings);
+        publisher.encoder = Some(encoder);
+        Ok(publisher)
+    }
+
+    /// Creates a publisher like [`Publisher::with_schema`] for a topic in the project of
+    /// `client`, which the topic and its schema are fetched through as well.
+    pub async fn with_client_and_schema(
+        client: &Client,
+        topic: impl Into<String>,
+        settings: BatchSettings,
+    ) -> Result<Publisher, Error> {
+        let topic = resource_name_in(client.project_id(), "topics", topic.into());
+        let encoder = SchemaEncoder::for_topic(Some(client), topic.clone()).await?;
+        let mut publisher = Publisher::with_client(client, topic, settings);
+        publisher.encoder = Some(encoder);
+        Ok(publisher)
+    }
+
+    /// Resumes publishing for an ordering key that was paused by a failed publish.
+    ///
+    /// After a batch with an ordering key fails, every buffered and future message with the same
+    /// key is rejected with [`Error::OrderingKeyPaused`] until this is called, so that messages
+    /// are never published out of order.
+    pub fn resume_publish(&self, ordering_key: impl Into<String>) {
+        let _ = self.sender.send(Command::Resume(ordering_key.into()));
+    }
+
+    /// Buffers `message` and returns a future that resolves to the message id assigned by the server.
+    pub fn publish(&self, message: Message) -> impl Future<Output = Result<String, Error>> {
+        let (sender, receiver) = oneshot::channel();
+        let outstanding = self.outstanding.track();
+        let sent = self
+            .sender
+            .send(Command::Publish(message.into_tonic(), sender))
+            .is_ok();
+        async move {
+            if !sent {
+                return Err(Error::Closed);
+            }
+            let result = receiver.await.unwrap_or(Err(Error::Closed));
+            drop(outstanding);
+            result
+        }
+    }
+
+    /// Encodes `payload` into a message that can be completed with attributes or an ordering key.
+    ///
+    /// The payload is encoded according to the topic schema for a publisher created with
+    /// [`Publisher::with_schema`], and as plain JSON otherwise.
+    pub fn encode<T: Serialize>(&self, payload: &T) -> Result<MessageBuilder, Error> {
+        match &self.encoder {
+            Some(encoder) => Ok(Message::builder().data(encoder.encode(payload)?)),
+            None => Message::builder().json(payload),
+        }
+    }
+
+    /// Encodes `payload` like [`Publisher::encode`] and publishes it.
+    pub fn publish_payload<T: Serialize>(
+        &self,
+        payload: &T,
+    ) -> impl Future<Output = Result<String, Error>> {
+        let published = self
+            .encode(payload)
+            .map(|message| self.publish(message.build()));
+        async move { published?.await }
+    }
+
+    /// Sends every buffered message and waits until all outstanding batches have completed.
+    pub async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+        if self.sender.send(Command::Flush(sender)).is_ok() {
+            let _ = receiver.await;
+        }
+    }
+
+    /// Flushes the buffered messages and stops the background task.
+    pub async fn shutdown(self) {
+        let Publisher { sender, handle, .. } = self;
+        drop(sender);
+        let _ = handle.await;
+    }
+}
+
+/// Where the batches of a publisher are sent.
+#[derive(Clone)]
+struct Destination {
+    topic: String,
+    client: Option<Client>,
+    options: CallOptions,
+}
+
+enum Command {
+    Publish(PubsubMessage, oneshot::Sender<Result<String, Error>>),
+    Flush(oneshot::Sender<()>),
+    Resume(String),
+}
+
+struct Batch {
+    ordering_key: String,
+    messages: Vec<PubsubMessage>,
+    senders: Vec<oneshot::Sender<Result<String, Error>>>,
+    bytes: usize,
+    deadline: Option<Instant>,
+}
+
+impl Batch {
+    fn new(ordering_key: impl Into<String>) -> Self {
+        Batch {
+            ordering_key: ordering_key.into(),
+            messages: Vec::new(),
+            senders: Vec::new(),
+            bytes: 0,
+            deadline: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn fits(&self, message: &PubsubMessage, settings: &BatchSettings) -> bool {
+        self.is_empty() || self.bytes + message.encoded_len() <= settings.max_bytes
+    }
+
+    fn push(
+        &mut self,
+        message: PubsubMessage,
+        sender: oneshot::Sender<Result<String, Error>>,
+        settings: &BatchSettings,
+    ) {
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + settings.delay);
+        }
+        self.bytes += message.encoded_len();
+        self.messages.push(message);
+        self.senders.push(sender);
+    }
+
+    fn is_full(&self, settings: &BatchSettings) -> bool {
+        self.messages.len() >= settings.max_messages || self.bytes >= settings.max_bytes
+    }
+
+    fn take(&mut self) -> Batch {
+        let ordering_key = self.ordering_key.clone();
+        mem::replace(self, Batch::new(ordering_key))
+    }
+
+    fn fail(self, err: Error) {
+        for sender in self.senders {
+            let _ = sender.send(Err(err.clone()));
+        }
+    }
+
+    /// Publishes the batch and returns its ordering key together with whether it succeeded.
+    async fn send(self, destination: Destination) -> (String, bool) {
+        let Batch {
+            ordering_key,
+            messages,
+            senders,
+            ..
+        } = self;
+        match publish_batch(destination, messages).await {
+            Ok(message_ids) => {
+                for (sender, message_id) in senders.into_iter().zip(message_ids) {
+                    let _ = sender.send(Ok(message_id));
+                }
+                (ordering_key, true)
+            }
+            Err(err) => {
+                for sender in senders {
+                    let _ = sender.send(Err(err.clone()));
+                }
+                (ordering_key, false)
+            }
+        }
+    }
+}
+
+/// The batches of a single ordering key.
+///
+/// Messages without an ordering key share the state for the empty key, whose batches are sent
+/// concurrently. For any other key at most one batch is in flight at a time, and a failure pauses
+/// the key until it is resumed explicitly.
+struct KeyState {
+    batch: Batch,
+    queue: VecDeque<Batch>,
+    in_flight: bool,
+    paused: bool,
+}
+
+impl KeyState {
+    fn new(ordering_key: &str) -> Self {
+        KeyState {
+            batch: Batch::new(ordering_key),
+            queue: VecDeque::new(),
+            in_flight: false,
+            paused: false,
+        }
+    }
+
+    fn is_ordered(&self) -> bool {
+        !self.batch.ordering_key.is_empty()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.batch.is_empty() && self.queue.is_empty() && !self.in_flight && !self.paused
+    }
+
+    fn enqueue(&mut self) {
+        if !self.batch.is_empty() {
+            let batch = self.batch.take();
+            self.queue.push_back(batch);
+        }
+    }
+
+    fn ready(&mut self) -> Vec<Batch> {
+        if !self.is_ordered() {
+            return self.queue.drain(..).collect();
+        }
+        if self.in_flight || self.paused {
+            return Vec::new();
+        }
+        match self.queue.pop_front() {
+            Some(batch) => {
+                self.in_flight = true;
+                vec![batch]
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+struct Batches {
+    settings: BatchSettings,
+    keys: HashMap<String, KeyState>,
+}
+
+impl Batches {
+    fn new(settings: BatchSettings) -> Self {
+        Batches {
+            settings,
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Buffers `message` and returns the batches that can be sent right away.
+    fn push(
+        &mut self,
+        message: PubsubMessage,
+        sender: oneshot::Sender<Result<String, Error>>,
+    ) -> Vec<Batch> {
+        let settings = &self.settings;
+        let key = message.ordering_key.clone();
+        let state = self
+            .keys
+            .entry(key.clone())
+            .or_insert_with(|| KeyState::new(&key));
+        if state.paused {
+            let _ = sender.send(Err(Error::OrderingKeyPaused(key)));
+            return Vec::new();
+        }
+        if !state.batch.fits(&message, settings) {
+            state.enqueue();
+        }
+        state.batch.push(message, sender, settings);
+        if state.batch.is_full(settings) {
+            state.enqueue();
+        }
+        state.ready()
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.keys
+            .values()
+            .filter_map(|state| state.batch.deadline)
+            .min()
+    }
+
+    /// Closes every batch whose delay has elapsed at `now`.
+    fn expire(&mut self, now: Instant) -> Vec<Batch> {
+        let mut ready = Vec::new();
+        for state in self.keys.values_mut() {
+            if matches!(state.batch.deadline, Some(deadline) if deadline <= now) {
+                state.enqueue();
+            }
+            ready.extend(state.ready());
+        }
+        ready
+    }
+
+    /// Closes every batch regardless of the thresholds.
+    fn drain(&mut self) -> Vec<Batch> {
+        let mut ready = Vec::new();
+        for state in self.keys.values_mut() {
+            state.enqueue();
+            ready.extend(state.ready());
+        }
+        ready
+    }
+
+    /// Records the outcome of a batch and returns the batches it unblocked.
+    fn complete(&mut self, ordering_key: &str, succeeded: bool) -> Vec<Batch> {
+        let state = match self.keys.get_mut(ordering_key) {
+            Some(state) => state,
+            None => return Vec::new(),
+        };
+        if !state.is_ordered() {
+            return Vec::new();
+        }
+        state.in_flight = false;
+        if !succeeded {
+            state.paused = true;
+            let paused = Error::OrderingKeyPaused(ordering_key.into());
+            state.enqueue();
+            for batch in state.queue.drain(..) {
+                batch.fail(paused.clone());
+            }
+        }
+        let ready = state.ready();
+        if state.is_idle() {
+            self.keys.remove(ordering_key);
+        }
+        ready
+    }
+
+    fn resume(&mut self, ordering_key: &str) {
+        if let Some(state) = self.keys.get_mut(ordering_key) {
+            state.paused = false;
+            if state.is_idle() {
+                self.keys.remove(ordering_key);
+            }
+        }
+    }
+}
+
+async fn publish_batch(
+    destination: Destination,
+    messages: Vec<PubsubMessage>,
+) -> Result<Vec<String>, Error> {
+    let mut client = PublisherClient::get_with(destination.client.as_ref()).await?;
+    let request = PublishRequest {
+        topic: destination.topic.clone(),
+        messages,
+    };
+    let options = destination.options.resource("topic", &destination.topic);
+    let response = retry::send(&options, request, |request| client.publish(request)).await?;
+    Ok(response.into_inner().message_ids)
+}
+
+async fn run(
+    destination: Destination,
+    settings: BatchSettings,
+    mut receiver: mpsc::UnboundedReceiver<Command>,
+) {
+    let mut batches = Batches::new(settings);
+    let mut in_flight = FuturesUnordered::new();
+    // Flushes complete the next time no batch is in flight, so the loop keeps serving commands
+    // while the batches they drained are sent.
+    let mut flushes: Vec<oneshot::Sender<()>> = Vec::new();
+    loop {
+        let deadline = batches.next_deadline();
+        let ready = tokio::select! {
+            command = receiver.recv() => match command {
+                Some(Command::Publish(message, sender)) => batches.push(message, sender),
+                Some(Command::Flush(done)) => {
+                    flushes.push(done);
+                    batches.drain()
+                }
+                Some(Command::Resume(ordering_key)) => {
+                    batches.resume(&ordering_key);
+                    Vec::new()
+                }
+                None => break,
+            },
+            _ = tokio::time::delay_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {
+                batches.expire(Instant::now())
+            }
+            Some((ordering_key, succeeded)) = in_flight.next(), if !in_flight.is_empty() => {
+                let ordering_key: String = ordering_key;
+                batches.complete(&ordering_key, succeeded)
+            }
+        };
+        for batch in ready {
+            in_flight.push(batch.send(destination.clone()));
+        }
+        if in_flight.is_empty() {
+            for done in flushes.drain(..) {
+                let _ = done.send(());
+            }
+        }
+    }
+    for batch in batches.drain() {
+        in_flight.push(batch.send(destination.clone()));
+    }
+    while let Some((ordering_key, succeeded)) = in_flight.next().await {
+        for batch in batches.complete(&ordering_key, succeeded) {
+            in_flight.push(batch.send(destination.clone()));
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{Batch, BatchSettings, Batches};
+    use crate::{
+        proto::google::pubsub::v1::PubsubMessage, service::google::pubsub::v1::error::Error,
+    };
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    fn message(size: usize) -> PubsubMessage {
+        PubsubMessage {
+            data: vec![0; size],
+            ..Default::default()
+        }
+    }
+
+    fn ordered(ordering_key: &str) -> PubsubMessage {
+        PubsubMessage {
+            ordering_key: ordering_key.into(),
+            ..message(1)
+        }
+    }
+
+    fn push(batch: &mut Batch, message: PubsubMessage, settings: &BatchSettings) {
+        let (sender, _) = oneshot::channel();
+        batch.push(message, sender, settings);
+    }
+
+    fn settings(max_messages: usize) -> BatchSettings {
+        BatchSettings {
+            max_messages,
+            max_bytes: 1000,
+            delay: Duration::from_secs(1),
+            ..BatchSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_count_threshold() {
+        let settings = settings(2);
+        let mut batch = Batch::new("");
+        assert!(batch.deadline.is_none());
+        push(&mut batch, message(1), &settings);
+        assert!(batch.deadline.is_some());
+        assert!(!batch.is_full(&settings));
+        push(&mut batch, message(1), &settings);
+        assert!(batch.is_full(&settings));
+
+        let taken = batch.take();
+        assert_eq!(2, taken.messages.len());
+        assert!(batch.is_empty());
+        assert!(batch.deadline.is_none());
+    }
+
+    #[test]
+    fn test_bytes_threshold() {
+        let settings = BatchSettings {
+            max_messages: 100,
+            max_bytes: 100,
+            delay: Duration::from_secs(1),
+            ..BatchSettings::default()
+        };
+        let mut batch = Batch::new("");
+        assert!(batch.fits(&message(200), &settings));
+        push(&mut batch, message(60), &settings);
+        assert!(!batch.is_full(&settings));
+        assert!(!batch.fits(&message(60), &settings));
+        push(&mut batch, message(60), &settings);
+        assert!(batch.is_full(&settings));
+    }
+
+    #[test]
+    fn test_unordered_batches_are_concurrent() {
+        let mut batches = Batches::new(settings(1));
+        assert_eq!(1, batches.push(message(1), oneshot::channel().0).len());
+        assert_eq!(1, batches.push(message(1), oneshot::channel().0).len());
+    }
+
+    #[test]
+    fn test_ordered_batches_are_sequential() {
+        let mut batches = Batches::new(settings(1));
+        let first = batches.push(ordered("a"), oneshot::channel().0);
+        assert_eq!(1, first.len());
+        assert!(batches.push(ordered("a"), oneshot::channel().0).is_empty());
+        assert_eq!(1, batches.push(ordered("b"), oneshot::channel().0).len());
+
+        let next = batches.complete("a", true);
+        assert_eq!(1, next.len());
+        assert_eq!("a", next[0].ordering_key);
+        assert!(batches.complete("a", true).is_empty());
+        assert!(!batches.keys.contains_key("a"));
+    }
+
+    #[test]
+    fn test_failure_pauses_ordering_key() {
+        let mut batches = Batches::new(settings(1));
+        assert_eq!(1, batches.push(ordered("a"), oneshot::channel().0).len());
+        let (sender, mut queued) = oneshot::channel();
+        assert!(batches.push(ordered("a"), sender).is_empty());
+
+        assert!(batches.complete("a", false).is_empty());
+        assert!(matches!(
+            queued.try_recv().unwrap(),
+            Err(Error::OrderingKeyPaused(key)) if key == "a"
+        ));
+
+        let (sender, mut rejected) = oneshot::channel();
+        assert!(batches.push(ordered("a"), sender).is_empty());
+        assert!(matches!(
+            rejected.try_recv().unwrap(),
+            Err(Error::OrderingKeyPaused(key)) if key == "a"
+        ));
+
+        batches.resume("a");
+        assert_eq!(1, batches.push(ordered("a"), oneshot::channel().0).len());
+    }
+}
@@ -0,0 +1,322 @@
+use super::{
+    avro::AvroSchema,
+    error::Error,
+    models::{Encoding, Schema, SchemaType},
+    protobuf::ProtoSchema,
+    schema_name,
+};
+use crate::{
+    config::project_id,
+    proto::google::pubsub::v1::{
+        self as pubsub, publisher_client::PublisherClient,
+        schema_service_client::SchemaServiceClient, validate_message_request::SchemaSpec,
+        CreateSchemaRequest, DeleteSchemaRequest, GetSchemaRequest, GetTopicRequest,
+        ListSchemasRequest, SchemaView, ValidateMessageRequest, ValidateSchemaRequest,
+    },
+    retry, CallOptions, Client,
+};
+use serde::Serialize;
+
+pub async fn create_schema(schema: Schema) -> Result<Schema, Error> {
+    create_schema_with_options(schema, CallOptions::default()).await
+}
+
+pub async fn create_schema_with_options(
+    schema: Schema,
+    options: CallOptions,
+) -> Result<Schema, Error> {
+    let mut client = SchemaServiceClient::get().await?;
+    let name = schema_name(schema.name.clone());
+    let (parent, schema_id) = split_name(&name);
+    let request = CreateSchemaRequest {
+        parent: parent.to_string(),
+        schema: Some(schema.into_tonic()),
+        schema_id: schema_id.to_string(),
+    };
+    let options = options.resource("parent", &request.parent);
+    let response = retry::send(&options, request, |request| client.create_schema(request)).await?;
+    Ok(Schema::from_tonic(response.into_inner()))
+}
+
+pub async fn get_schema(schema: impl Into<String>) -> Result<Schema, Error> {
+    get_schema_with_options(schema, CallOptions::default()).await
+}
+
+pub async fn get_schema_with_options(
+    schema: impl Into<String>,
+    options: CallOptions,
+) -> Result<Schema, Error> {
+    let client = SchemaServiceClient::get().await?;
+    let request = GetSchemaRequest {
+        name: schema_name(schema),
+        view: SchemaView::Full as i32,
+    };
+    let options = options.resource("name", &request.name);
+    let response = retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.get_schema(request).await },
+    )
+    .await?;
+    Ok(Schema::from_tonic(response.into_inner()))
+}
+
+pub async fn list_schemas() -> Result<Vec<Schema>, Error> {
+    list_schemas_with_options(CallOptions::default()).await
+}
+
+/// Lists the schemas of the project, with `options` applied to the request of every page.
+pub async fn list_schemas_with_options(options: CallOptions) -> Result<Vec<Schema>, Error> {
+    let client = SchemaServiceClient::get().await?;
+    let parent = format!("projects/{}", project_id());
+    let options = options.resource("parent", &parent);
+    let mut schemas = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let request = ListSchemasRequest {
+            parent: parent.clone(),
+            view: SchemaView::Full as i32,
+            page_token,
+            ..Default::default()
+        };
+        let response = retry::call(
+            &options,
+            None,
+            &client,
+            request,
+            |mut client, request| async move { client.list_schemas(request).await },
+        )
+        .await?
+        .into_inner();
+        schemas.extend(response.schemas.into_iter().map(Schema::from_tonic));
+        if response.next_page_token.is_empty() {
+            return Ok(schemas);
+        }
+        page_token = response.next_page_token;
+    }
+}
+
+pub async fn delete_schema(schema: impl Into<String>) -> Result<(), Error> {
+    delete_schema_with_options(schema, CallOptions::default()).await
+}
+
+pub async fn delete_schema_with_options(
+    schema: impl Into<String>,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let client = SchemaServiceClient::get().await?;
+    let request = DeleteSchemaRequest {
+        name: schema_name(schema),
+    };
+    let options = options.resource("name", &request.name);
+    retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.delete_schema(request).await },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Asks the server whether `schema` is a valid definition, without creating it.
+pub async fn validate_schema(schema: Schema) -> Result<(), Error> {
+    validate_schema_with_options(schema, CallOptions::default()).await
+}
+
+pub async fn validate_schema_with_options(
+    schema: Schema,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let client = SchemaServiceClient::get().await?;
+    let request = ValidateSchemaRequest {
+        parent: format!("projects/{}", project_id()),
+        schema: Some(schema.into_tonic()),
+    };
+    let options = options.resource("parent", &request.parent);
+    retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.validate_schema(request).await },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Asks the server whether `message` is valid for the schema named `schema`.
+pub async fn validate_message(
+    schema: impl Into<String>,
+    message: impl Into<Vec<u8>>,
+    encoding: Encoding,
+) -> Result<(), Error> {
+    validate_message_with_options(schema, message, encoding, CallOptions::default()).await
+}
+
+pub async fn validate_message_with_options(
+    schema: impl Into<String>,
+    message: impl Into<Vec<u8>>,
+    encoding: Encoding,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let client = SchemaServiceClient::get().await?;
+    let name = schema_name(schema);
+    let (parent, _) = split_name(&name);
+    let request = ValidateMessageRequest {
+        parent: parent.to_string(),
+        message: message.into(),
+        encoding: encoding.into_tonic(),
+        schema_spec: Some(SchemaSpec::Name(name)),
+    };
+    let options = options.resource("parent", &request.parent);
+    retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.validate_message(request).await },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Splits `projects/{project}/schemas/{schema}` into the project name and the schema id.
+fn split_name(name: &str) -> (&str, &str) {
+    match name.find("/schemas/") {
+        Some(index) => (&name[..index], &name[index + "/schemas/".len()..]),
+        None => (name, ""),
+    }
+}
+
+/// Encodes payloads for a topic according to its schema settings.
+///
+/// Payloads are checked against the schema locally, so that a payload that does not match fails
+/// before it is published.
+pub(crate) struct SchemaEncoder {
+    schema: String,
+    definition: Definition,
+    encoding: Encoding,
+}
+
+enum Definition {
+    Avro(AvroSchema),
+    ProtocolBuffer(ProtoSchema),
+}
+
+impl SchemaEncoder {
+    /// Fetches the schema settings of `topic` and its schema through `client`.
+    pub(crate) async fn for_topic(
+        client: Option<&Client>,
+        topic: String,
+    ) -> Result<SchemaEncoder, Error> {
+        let publisher = PublisherClient::get_with(client).await?;
+        let request = GetTopicRequest { topic };
+        let options = CallOptions::default().resource("topic", &request.topic);
+        let topic = retry::call(
+            &options,
+            client,
+            &publisher,
+            request,
+            |mut client, request| async move { client.get_topic(request).await },
+        )
+        .await?;
+        let (request, encoding) = schema_request(topic.into_inner())?;
+        let schemas = SchemaServiceClient::get_with(client).await?;
+        let options = CallOptions::default().resource("name", &request.name);
+        let schema = retry::call(
+            &options,
+            client,
+            &schemas,
+            request,
+            |mut client, request| async move { client.get_schema(request).await },
+        )
+        .await?;
+        SchemaEncoder::new(Schema::from_tonic(schema.into_inner()), encoding)
+    }
+
+    pub(crate) fn new(schema: Schema, encoding: Encoding) -> Result<SchemaEncoder, Error> {
+        let invalid = |e| Error::Schema(format!("The schema {} is invalid: {}", schema.name, e));
+        let definition = match schema.schema_type {
+            SchemaType::Avro => {
+                Definition::Avro(AvroSchema::parse(&schema.definition).map_err(invalid)?)
+            }
+            SchemaType::ProtocolBuffer => {
+                Definition::ProtocolBuffer(ProtoSchema::parse(&schema.definition).map_err(invalid)?)
+            }
+        };
+        Ok(SchemaEncoder {
+            schema: schema.name,
+            definition,
+            encoding,
+        })
+    }
+
+    pub(crate) fn encode<T: Serialize>(&self, payload: &T) -> Result<Vec<u8>, Error> {
+        let value = serde_json::to_value(payload)?;
+        let encoded = match (&self.definition, self.encoding) {
+            (Definition::Avro(avro), Encoding::Json) => avro
+                .to_json(&value)
+                .map(|value| serde_json::to_vec(&value).unwrap_or_else(|_| common_panic!())),
+            (Definition::Avro(avro), Encoding::Binary) => avro.to_binary(&value),
+            (Definition::ProtocolBuffer(proto), Encoding::Json) => proto
+                .check(&value)
+                .map(|()| serde_json::to_vec(&value).unwrap_or_else(|_| common_panic!())),
+            (Definition::ProtocolBuffer(proto), Encoding::Binary) => proto.to_binary(&value),
+        };
+        encoded.map_err(|e| {
+            Error::Schema(format!(
+                "The payload does not match the schema {}: {}",
+                self.schema, e
+            ))
+        })
+    }
+}
+
+/// The request for the schema of `topic`, with the encoding of its messages.
+///
+/// The schema is requested by the full resource name in the settings of the topic, which may
+/// belong to another project than the topic.
+fn schema_request(topic: pubsub::Topic) -> Result<(GetSchemaRequest, Encoding), Error> {
+    let name = topic.name;
+    let settings = topic
+        .schema_settings
+        .ok_or_else(|| Error::Schema(format!("The topic {} has no schema", name)))?;
+    let request = GetSchemaRequest {
+        name: settings.schema,
+        view: SchemaView::Full as i32,
+    };
+    Ok((request, Encoding::from_tonic(settings.encoding)))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{schema_request, Encoding};
+    use crate::proto::google::pubsub::v1 as pubsub;
+
+    #[test]
+    fn test_schema_request() {
+        let topic = pubsub::Topic {
+            name: "projects/p/topics/t".into(),
+            schema_settings: Some(pubsub::SchemaSettings {
+                schema: "projects/q/schemas/s".into(),
+                encoding: pubsub::Encoding::Binary as i32,
+            }),
+            ..Default::default()
+        };
+        let (request, encoding) = schema_request(topic).unwrap();
+        assert_eq!("projects/q/schemas/s", request.name);
+        assert_eq!(Encoding::Binary, encoding);
+
+        let topic = pubsub::Topic {
+            name: "projects/p/topics/t".into(),
+            ..Default::default()
+        };
+        assert!(schema_request(topic).is_err());
+    }
+}
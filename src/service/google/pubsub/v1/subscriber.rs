@@ -0,0 +1,615 @@
+use super::{
+    error::{AckError, Error},
+    models::{DeadLetterPolicy, Message, ReceivedMessage},
+    publisher::{BatchSettings, Publisher},
+    resource_name_in, subscription_name,
+};
+use crate::{
+    proto::google::pubsub::v1::{
+        subscriber_client::SubscriberClient, AcknowledgeRequest, ModifyAckDeadlineRequest,
+        PullRequest, StreamingPullRequest, StreamingPullResponse,
+    },
+    recorder::Outstanding,
+    retry::{self, Backoff},
+    CallOptions, Client, ErrorDetails,
+};
+use futures::{stream, Stream, StreamExt};
+use std::{
+    cmp,
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tonic::{codec::Streaming, Code};
+
+/// The `ErrorInfo` reason of failures that carry a result per ack id.
+const EXACTLY_ONCE_ACK_ID_FAILURE: &str = "EXACTLY_ONCE_ACKID_FAILURE";
+
+/// The waits before reconnecting a stream that failed with a transient error.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How long the delivery attempts of a message are remembered after its last delivery, in case
+/// it is acknowledged by another subscriber or expires instead of being redelivered.
+const ATTEMPTS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Pulls at most `max_messages` messages from `subscription` in a single request.
+///
+/// The result may contain fewer messages than requested, or none at all, even when more
+/// messages are available.
+pub async fn pull(
+    subscription: impl Into<String>,
+    max_messages: i32,
+) -> Result<Vec<ReceivedMessage>, Error> {
+    pull_with_options(subscription, max_messages, CallOptions::default()).await
+}
+
+pub async fn pull_with_options(
+    subscription: impl Into<String>,
+    max_messages: i32,
+    options: CallOptions,
+) -> Result<Vec<ReceivedMessage>, Error> {
+    let client = SubscriberClient::get().await?;
+    let request = PullRequest {
+        subscription: subscription_name(subscription),
+        max_messages,
+        ..Default::default()
+    };
+    let subscription = request.subscription.clone();
+    let options = options.resource("subscription", &subscription);
+    let response = retry::call(
+        &options,
+        None,
+        &client,
+        request,
+        |mut client, request| async move { client.pull(request).await },
+    )
+    .await?;
+    Ok(response
+        .into_inner()
+        .received_messages
+        .into_iter()
+        .map(|received| ReceivedMessage::from_tonic(subscription.clone(), received))
+        .collect())
+}
+
+pub async fn acknowledge<S: Into<String>>(
+    subscription: impl Into<String>,
+    ack_ids: impl IntoIterator<Item = S>,
+) -> Result<(), Error> {
+    acknowledge_with_options(subscription, ack_ids, CallOptions::default()).await
+}
+
+pub async fn acknowledge_with_options<S: Into<String>>(
+    subscription: impl Into<String>,
+    ack_ids: impl IntoIterator<Item = S>,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let mut client = SubscriberClient::get().await?;
+    let request = AcknowledgeRequest {
+        subscription: subscription_name(subscription),
+        ack_ids: ack_ids.into_iter().map(Into::into).collect(),
+    };
+    let options = options.resource("subscription", &request.subscription);
+    retry::send(&options, request, |request| client.acknowledge(request)).await?;
+    Ok(())
+}
+
+/// Extends the ack deadline of the messages to `seconds` from now.
+/// Passing `0` makes the messages immediately available for redelivery.
+pub async fn modify_ack_deadline<S: Into<String>>(
+    subscription: impl Into<String>,
+    ack_ids: impl IntoIterator<Item = S>,
+    seconds: i32,
+) -> Result<(), Error> {
+    modify_ack_deadline_with_options(subscription, ack_ids, seconds, CallOptions::default()).await
+}
+
+pub async fn modify_ack_deadline_with_options<S: Into<String>>(
+    subscription: impl Into<String>,
+    ack_ids: impl IntoIterator<Item = S>,
+    seconds: i32,
+    options: CallOptions,
+) -> Result<(), Error> {
+    let mut client = SubscriberClient::get().await?;
+    let request = ModifyAckDeadlineRequest {
+        subscription: subscription_name(subscription),
+        ack_ids: ack_ids.into_iter().map(Into::into).collect(),
+        ack_deadline_seconds: seconds,
+    };
+    let options = options.resource("subscription", &request.subscription);
+    retry::send(&options, request, |request| {
+        client.modify_ack_deadline(request)
+    })
+    .await?;
+    Ok(())
+}
+
+/// Settings of the stream a [`Subscriber`] receives messages from.
+#[derive(Debug, Clone)]
+pub struct SubscriberSettings {
+    /// The ack deadline of the messages received from the stream, between 10 and 600 seconds.
+    pub ack_deadline: Duration,
+    /// The server stops delivering messages while this many are unacknowledged.
+    pub max_outstanding_messages: i64,
+    /// The server stops delivering messages while this many bytes are unacknowledged.
+    pub max_outstanding_bytes: i64,
+}
+
+impl Default for SubscriberSettings {
+    fn default() -> Self {
+        SubscriberSettings {
+            ack_deadline: Duration::from_secs(10),
+            max_outstanding_messages: 1000,
+            max_outstanding_bytes: 100 * 1024 * 1024,
+        }
+    }
+}
+
+/// Receives the messages of a subscription over a streaming pull.
+///
+/// Every message has to be acknowledged with [`ReceivedMessage::ack`] before its ack deadline
+/// expires, or it is delivered again.
+pub struct Subscriber {
+    subscription: String,
+    settings: SubscriberSettings,
+    dead_letter_policy: Option<DeadLetterPolicy>,
+    client: Option<Client>,
+}
+
+impl Subscriber {
+    pub fn new(subscription: impl Into<String>) -> Subscriber {
+        Subscriber::with_settings(subscription, SubscriberSettings::default())
+    }
+
+    pub fn with_settings(
+        subscription: impl Into<String>,
+        settings: SubscriberSettings,
+    ) -> Subscriber {
+        Subscriber {
+            subscription: subscription_name(subscription),
+            settings,
+            dead_letter_policy: None,
+            client: None,
+        }
+    }
+
+    /// Creates a subscriber for a subscription in the project of `client`.
+    ///
+    /// Messages forwarded to a dead-letter topic are published through `client` as well.
+    pub fn with_client(
+        client: &Client,
+        subscription: impl Into<String>,
+        settings: SubscriberSettings,
+    ) -> Subscriber {
+        Subscriber {
+            subscription: resource_name_in(
+                client.project_id(),
+                "subscriptions",
+                subscription.into(),
+            ),
+            settings,
+            dead_letter_policy: None,
+            client: Some(client.clone()),
+        }
+    }
+
+    /// Forwards the messages delivered more than `max_delivery_attempts` times to the dead-letter
+    /// topic of `policy` and acknowledges them, instead of returning them from the stream.
+    ///
+    /// This is meant for subscriptions without a dead-letter policy on the server. The server only
+    /// counts delivery attempts when it has one, so otherwise they are counted by this subscriber
+    /// per message id, starting over whenever the subscriber is recreated.
+    pub fn dead_letter_policy(mut self, policy: DeadLetterPolicy) -> Self {
+        self.dead_letter_policy = Some(policy);
+        self
+    }
+
+    /// Starts receiving messages.
+    ///
+    /// The stream reconnects after transient failures and ends after returning any other failure.
+    pub fn stream(self) -> impl Stream<Item = Result<ReceivedMessage, Error>> {
+        stream::unfold(self.stream_state(), StreamState::next)
+    }
+
+    fn stream_state(self) -> StreamState {
+        StreamState {
+            outstanding: Outstanding::new(
+                self.subscription.clone(),
+                self.client.as_ref().and_then(Client::metrics_recorder),
+            ),
+            subscriber: self,
+            stream: None,
+            buffer: VecDeque::new(),
+            attempts: Arc::new(DeliveryAttempts::default()),
+            dead_letter_publisher: None,
+            backoff: INITIAL_BACKOFF,
+            finished: false,
+        }
+    }
+}
+
+struct StreamState {
+    subscriber: Subscriber,
+    stream: Option<Streaming<StreamingPullResponse>>,
+    buffer: VecDeque<ReceivedMessage>,
+    attempts: Arc<DeliveryAttempts>,
+    dead_letter_publisher: Option<Publisher>,
+    /// The messages received over the stream that have not been acknowledged yet.
+    outstanding: Arc<Outstanding>,
+    backoff: Duration,
+    finished: bool,
+}
+
+impl StreamState {
+    async fn next(mut self) -> Option<(Result<ReceivedMessage, Error>, Self)> {
+        loop {
+            if self.finished {
+                return None;
+            }
+            if let Some(message) = self.buffer.pop_front() {
+                match self.dead_letter(message).await {
+                    Ok(Some(message)) => return Some((Ok(message), self)),
+                    Ok(None) => continue,
+                    Err(err) => return Some((Err(err), self)),
+                }
+            }
+            let result = match self.stream.as_mut() {
+                Some(stream) => stream.message().await.map_err(Error::from),
+                None => match self.connect().await {
+                    Ok(stream) => {
+                        self.stream = Some(stream);
+                        continue;
+                    }
+                    Err(err) => Err(err),
+                },
+            };
+            match result {
+                Ok(Some(response)) => {
+                    self.backoff = INITIAL_BACKOFF;
+                    let subscription = &self.subscriber.subscription;
+                    let outstanding = &self.outstanding;
+                    let client = &self.subscriber.client;
+                    self.buffer
+                        .extend(response.received_messages.into_iter().map(|received| {
+                            ReceivedMessage::from_tonic(subscription.clone(), received)
+                                .tracked(outstanding.track())
+                                .received_with(client.clone())
+                        }));
+                }
+                Ok(None) => self.stream = None,
+                Err(err) if is_transient(&err) => {
+                    self.stream = None;
+                    tokio::time::delay_for(self.backoff).await;
+                    self.backoff = cmp::min(self.backoff * 2, MAX_BACKOFF);
+                }
+                Err(err) => {
+                    self.finished = true;
+                    return Some((Err(err), self));
+                }
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Streaming<StreamingPullResponse>, Error> {
+        let mut client = SubscriberClient::get_with(self.subscriber.client.as_ref()).await?;
+        let settings = &self.subscriber.settings;
+        let request = StreamingPullRequest {
+            subscription: self.subscriber.subscription.clone(),
+            stream_ack_deadline_seconds: settings.ack_deadline.as_secs() as i32,
+            max_outstanding_messages: settings.max_outstanding_messages,
+            max_outstanding_bytes: settings.max_outstanding_bytes,
+            ..Default::default()
+        };
+        // Acknowledgements are sent with separate requests, so nothing follows the initial request,
+        // but the stream has to stay open to keep receiving messages.
+        let requests = stream::iter(vec![request]).chain(stream::pending());
+        let options =
+            CallOptions::default().resource("subscription", &self.subscriber.subscription);
+        let response =
+            retry::send(&options, requests, |request| client.streaming_pull(request)).await?;
+        Ok(response.into_inner())
+    }
+
+    fn attempt(&self, message: &ReceivedMessage) -> i32 {
+        match message.delivery_attempt() {
+            Some(attempt) => attempt,
+            None => self.attempts.count(message.message_id()),
+        }
+    }
+
+    /// Returns the message, unless it has run out of delivery attempts and has been forwarded to
+    /// the dead-letter topic instead.
+    async fn dead_letter(
+        &mut self,
+        message: ReceivedMessage,
+    ) -> Result<Option<ReceivedMessage>, Error> {
+        let policy = match &self.subscriber.dead_letter_policy {
+            Some(policy) => policy.clone(),
+            None => return Ok(Some(message)),
+        };
+        let attempt = self.attempt(&message);
+        if attempt <= policy.max_delivery_attempts {
+            if message.delivery_attempt().is_some() {
+                return Ok(Some(message));
+            }
+            return Ok(Some(message.counted(self.attempts.clone())));
+        }
+        let forwarded = Message::builder()
+            .data(message.message().data())
+            .attributes(message.message().attributes().clone())
+            .attribute(
+                "CloudPubSubDeadLetterSourceSubscription",
+                self.subscriber.subscription.clone(),
+            )
+            .attribute(
+                "CloudPubSubDeadLetterSourceDeliveryCount",
+                attempt.to_string(),
+            )
+            .build();
+        let client = self.subscriber.client.as_ref();
+        let publisher = self
+            .dead_letter_publisher
+            .get_or_insert_with(|| match client {
+                Some(client) => Publisher::with_client(
+                    client,
+                    policy.dead_letter_topic,
+                    BatchSettings::default(),
+                ),
+                None => Publisher::new(policy.dead_letter_topic),
+            });
+        publisher.publish(forwarded).await?;
+        self.attempts.forget(message.message_id());
+        // A failed acknowledgement only means that the message is forwarded again once it is
+        // redelivered.
+        let _ = message.ack().await;
+        Ok(None)
+    }
+}
+
+/// The delivery attempts counted by a subscriber per message id, for messages that the server
+/// does not count.
+///
+/// A message is forgotten once it is acknowledged, or once it has not been delivered again for
+/// [`ATTEMPTS_TTL`].
+#[derive(Default)]
+pub(crate) struct DeliveryAttempts {
+    counts: Mutex<HashMap<String, (i32, Instant)>>,
+}
+
+impl DeliveryAttempts {
+    /// Counts a delivery of the message and returns the number of deliveries so far.
+    fn count(&self, message_id: &str) -> i32 {
+        let now = Instant::now();
+        let mut counts = self.counts.lock().unwrap();
+        counts.retain(|_, (_, delivered)| now.duration_since(*delivered) < ATTEMPTS_TTL);
+        let (count, delivered) = counts.entry(message_id.to_string()).or_insert((0, now));
+        *count += 1;
+        *delivered = now;
+        *count
+    }
+
+    pub(crate) fn forget(&self, message_id: &str) {
+        self.counts.lock().unwrap().remove(message_id);
+    }
+}
+
+impl std::fmt::Debug for DeliveryAttempts {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DeliveryAttempts").finish()
+    }
+}
+
+/// Acknowledges, or with `seconds` modifies the ack deadline of, each of `ack_ids` and returns
+/// the result for every one of them.
+///
+/// Failures the server reports as transient are retried according to the retry policy of
+/// `client`, only for the ack ids that failed.
+pub(crate) async fn confirm(
+    client: Option<&Client>,
+    subscription: String,
+    ack_ids: Vec<String>,
+    seconds: Option<i32>,
+) -> HashMap<String, Result<(), AckError>> {
+    let mut backoff = Backoff::new(CallOptions::default().policy(client));
+    let mut results = HashMap::new();
+    let mut pending = ack_ids;
+    loop {
+        let result = send_confirm(client, &subscription, pending.clone(), seconds).await;
+        let mut failures = match result {
+            Ok(()) => HashMap::new(),
+            Err(err) => ack_failures(&pending, err),
+        };
+        let wait = backoff.next(None, None);
+        let can_retry = wait.is_some();
+        let mut retry = Vec::new();
+        for ack_id in pending {
+            match failures.remove(&ack_id) {
+                None => {
+                    results.insert(ack_id, Ok(()));
+                }
+                Some((_, true)) if can_retry => retry.push(ack_id),
+                Some((err, _)) => {
+                    results.insert(ack_id, Err(err));
+                }
+            }
+        }
+        match wait {
+            Some(wait) if !retry.is_empty() => tokio::time::delay_for(wait).await,
+            _ => return results,
+        }
+        pending = retry;
+    }
+}
+
+/// Sends a single acknowledgement, or with `seconds` ack deadline modification, request.
+async fn send_confirm(
+    client: Option<&Client>,
+    subscription: &str,
+    ack_ids: Vec<String>,
+    seconds: Option<i32>,
+) -> Result<(), Error> {
+    let mut grpc_client = SubscriberClient::get_with(client).await?;
+    let options = CallOptions::default().resource("subscription", subscription);
+    match seconds {
+        None => {
+            let request = AcknowledgeRequest {
+                subscription: subscription.to_string(),
+                ack_ids,
+            };
+            retry::send(&options, request, |request| {
+                grpc_client.acknowledge(request)
+            })
+            .await?
+        }
+        Some(seconds) => {
+            let request = ModifyAckDeadlineRequest {
+                subscription: subscription.to_string(),
+                ack_ids,
+                ack_deadline_seconds: seconds,
+            };
+            retry::send(&options, request, |request| {
+                grpc_client.modify_ack_deadline(request)
+            })
+            .await?
+        }
+    };
+    Ok(())
+}
+
+/// Maps a failed request to the ack ids that failed, each with whether the failure is transient.
+/// Ack ids that are missing from the result succeeded.
+fn ack_failures(ack_ids: &[String], err: Error) -> HashMap<String, (AckError, bool)> {
+    if let Error::Status(status) = &err {
+        if let Some(info) = ErrorDetails::from_status(status).error_info {
+            if info.reason == EXACTLY_ONCE_ACK_ID_FAILURE {
+                return info
+                    .metadata
+                    .into_iter()
+                    .map(|(ack_id, reason)| {
+                        let failure = match reason.as_str() {
+                            "PERMANENT_FAILURE_INVALID_ACK_ID" => (AckError::InvalidAckId, false),
+                            _ if reason.starts_with("TRANSIENT_") => {
+                                (AckError::Other(reason), true)
+                            }
+                            _ => (AckError::Other(reason), false),
+                        };
+                        (ack_id, failure)
+                    })
+                    .collect();
+            }
+        }
+    }
+    let failure = match &err {
+        Error::Status(status) if status.code() == Code::PermissionDenied => {
+            (AckError::PermissionDenied, false)
+        }
+        Error::Status(status) if status.code() == Code::FailedPrecondition => {
+            (AckError::FailedPrecondition, false)
+        }
+        _ => (AckError::Request(err.clone()), is_transient(&err)),
+    };
+    ack_ids
+        .iter()
+        .map(|ack_id| (ack_id.clone(), failure.clone()))
+        .collect()
+}
+
+/// Acknowledgements are safe to send again, so internal errors are retried as well.
+fn is_transient(err: &Error) -> bool {
+    let internal = matches!(err, Error::Status(status) if status.code() == Code::Internal || status.code() == Code::Unknown);
+    internal || err.is_retryable()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{ack_failures, DeadLetterPolicy, Subscriber, EXACTLY_ONCE_ACK_ID_FAILURE};
+    use crate::{
+        proto::google::{pubsub::v1 as pubsub, rpc},
+        service::google::pubsub::v1::{
+            error::{AckError, Error},
+            models::ReceivedMessage,
+        },
+    };
+    use prost::Message;
+    use tonic::{Code, Status};
+
+    const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+
+    fn status_with_error_info(metadata: Vec<(&str, &str)>) -> Status {
+        let info = rpc::ErrorInfo {
+            reason: EXACTLY_ONCE_ACK_ID_FAILURE.into(),
+            domain: "pubsub.googleapis.com".into(),
+            metadata: metadata
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        let mut value = Vec::new();
+        info.encode(&mut value).unwrap();
+        let details = rpc::Status {
+            code: Code::InvalidArgument as i32,
+            message: "Some acknowledgement ids in the request were invalid.".into(),
+            details: vec![prost_types::Any {
+                type_url: ERROR_INFO_TYPE_URL.into(),
+                value,
+            }],
+        };
+        let mut bytes = Vec::new();
+        details.encode(&mut bytes).unwrap();
+        Status::with_details(Code::InvalidArgument, details.message, bytes.into())
+    }
+
+    #[test]
+    fn test_per_ack_id_failures() {
+        let ack_ids = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let status = status_with_error_info(vec![
+            ("a", "PERMANENT_FAILURE_INVALID_ACK_ID"),
+            ("b", "TRANSIENT_FAILURE_UNORDERED_ACK_ID"),
+        ]);
+        let failures = ack_failures(&ack_ids, Error::from(status));
+        assert_eq!(2, failures.len());
+        assert!(matches!(failures["a"], (AckError::InvalidAckId, false)));
+        assert!(matches!(failures["b"], (AckError::Other(_), true)));
+        assert!(!failures.contains_key("c"));
+    }
+
+    #[test]
+    fn test_request_failures() {
+        let ack_ids = vec!["a".to_string(), "b".to_string()];
+        let failures = ack_failures(&ack_ids, Error::from(Status::unavailable("")));
+        assert!(matches!(failures["a"], (AckError::Request(_), true)));
+        assert!(matches!(failures["b"], (AckError::Request(_), true)));
+
+        let failures = ack_failures(&ack_ids, Error::from(Status::permission_denied("")));
+        assert!(matches!(failures["a"], (AckError::PermissionDenied, false)));
+    }
+
+    #[test]
+    fn test_delivery_attempts() {
+        let subscriber =
+            Subscriber::with_settings("projects/p/subscriptions/s", Default::default())
+                .dead_letter_policy(DeadLetterPolicy::new("projects/p/topics/dlq", 5));
+        let state = subscriber.stream_state();
+        let received = |delivery_attempt| {
+            ReceivedMessage::from_tonic(
+                "projects/p/subscriptions/s".into(),
+                pubsub::ReceivedMessage {
+                    ack_id: "ack".into(),
+                    message: Some(pubsub::PubsubMessage {
+                        message_id: "1".into(),
+                        ..Default::default()
+                    }),
+                    delivery_attempt,
+                },
+            )
+        };
+        assert_eq!(1, state.attempt(&received(0)));
+        assert_eq!(2, state.attempt(&received(0)));
+        assert_eq!(7, state.attempt(&received(7)));
+        state.attempts.forget("1");
+        assert_eq!(1, state.attempt(&received(0)));
+    }
+}
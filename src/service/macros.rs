@@ -3,10 +3,13 @@ macro_rules! define_client {
         struct ChannelInitializer {}
         #[async_trait::async_trait]
         impl crate::util::init_once::AsyncInitializer for ChannelInitializer {
-            type T = tonic::transport::Channel;
-            type Error = tonic::transport::Error;
-            async fn create(&self) -> Result<tonic::transport::Channel, tonic::transport::Error> {
-                Ok(crate::service::create_channel(DOMAIN).await?)
+            type T = crate::service::Transport;
+            type Error = crate::Error;
+            async fn create(&self) -> Result<crate::service::Transport, crate::Error> {
+                let emulator_host = EMULATOR_HOST.read().unwrap().clone();
+                let settings = CHANNEL_SETTINGS.read().unwrap().clone();
+                let endpoint = crate::service::EndpointSettings::default();
+                crate::service::create_channel(SERVICE.domain(), emulator_host, &endpoint, &settings, None).await
             }
         }
 
@@ -16,17 +19,56 @@ macro_rules! define_client {
             crate::util::init_once::AsyncInitOnce::new(ChannelInitializer {})
         });
 
+        type ChannelSettingsHolder =
+            once_cell::sync::Lazy<std::sync::RwLock<crate::service::ChannelSettings>>;
+        static CHANNEL_SETTINGS: ChannelSettingsHolder =
+            once_cell::sync::Lazy::new(|| std::sync::RwLock::new(Default::default()));
+
+        /// Configures the channels of this service. It must be called before the first request.
+        pub fn set_channel_settings(settings: crate::service::ChannelSettings) {
+            *CHANNEL_SETTINGS.write().unwrap() = settings;
+        }
+
+        type EmulatorHostHolder = once_cell::sync::Lazy<std::sync::RwLock<Option<String>>>;
+        static EMULATOR_HOST: EmulatorHostHolder = once_cell::sync::Lazy::new(|| {
+            std::sync::RwLock::new(crate::service::emulator_host_from_env(SERVICE.emulator_host_var()))
+        });
+
+        /// Connects this service to an emulator at `host`, such as `localhost:8085`, over plaintext
+        /// and without credentials.
+        ///
+        /// This is also enabled by the emulator host environment variable of the service. It must
+        /// be called before the first request.
+        pub fn use_emulator(host: impl Into<String>) {
+            *EMULATOR_HOST.write().unwrap() = Some(host.into());
+        }
+
         $(
-            impl $type<tonic::transport::Channel> {
+            impl $type<crate::service::Transport> {
+                // Some services are only used through `get_with`.
+                #[allow(dead_code)]
                 pub(crate) async fn get() -> Result<Self, Error> {
-                    let channel = CHANNEL.get().await?.clone();
-                    let token = crate::service::get_token(&[SCOPE]).await?;
+                    Self::get_with(None).await
+                }
+
+                /// Connects through `client`, or through the global settings of this service.
+                pub(crate) async fn get_with(client: Option<&crate::Client>) -> Result<Self, Error> {
+                    let (channel, credentials) = match client {
+                        Some(client) => {
+                            let channel = client.channel(SERVICE).await?;
+                            (channel, client.credentials(SERVICE, &[SCOPE]).await?)
+                        }
+                        None => {
+                            let channel = CHANNEL.get().await?.clone();
+                            let credentials =
+                                crate::service::get_credentials(&EMULATOR_HOST, &[SCOPE]).await?;
+                            (channel, credentials)
+                        }
+                    };
 
-                    let bearer = format!("Bearer {}", token.as_str());
-                    let header_value = tonic::metadata::MetadataValue::from_str(&bearer).unwrap();
                     let client = Self::with_interceptor(channel, move |mut req: tonic::Request<()>| {
                         req.metadata_mut()
-                            .insert("authorization", header_value.clone());
+                            .insert("authorization", credentials.authorization().map_err(|status| *status)?);
                         Ok(req)
                     });
                     Ok(client)
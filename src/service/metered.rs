@@ -0,0 +1,192 @@
+use crate::recorder::{CallMetrics, MetricsRecorder};
+use bytes::Bytes;
+use hyper::body::HttpBody;
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tonic::Code;
+
+/// The metrics of an attempt, which are recorded once both its request and response have ended.
+pub(crate) struct CallRecord {
+    recorder: Arc<dyn MetricsRecorder>,
+    service: String,
+    method: String,
+    attempt: u32,
+    started: Instant,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    /// The status and latency of the attempt, once its response has ended.
+    ended: Mutex<Option<(Code, Duration)>>,
+}
+
+impl CallRecord {
+    /// Starts recording an attempt of the method at `path`, such as
+    /// `/google.pubsub.v1.Publisher/Publish`.
+    pub(crate) fn start(
+        recorder: Arc<dyn MetricsRecorder>,
+        path: &str,
+        attempt: u32,
+    ) -> Arc<CallRecord> {
+        let mut path = path.trim_start_matches('/').splitn(2, '/');
+        Arc::new(CallRecord {
+            recorder,
+            service: path.next().unwrap_or("").to_string(),
+            method: path.next().unwrap_or("").to_string(),
+            attempt,
+            started: Instant::now(),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            ended: Mutex::new(None),
+        })
+    }
+
+    pub(crate) fn end(&self, code: Code) {
+        let mut ended = self.ended.lock().unwrap();
+        if ended.is_none() {
+            *ended = Some((code, self.started.elapsed()));
+        }
+    }
+
+    /// Ends the attempt with the `grpc-status` in `headers`, if there is one.
+    pub(crate) fn end_with(&self, headers: &http::HeaderMap) {
+        let code = headers
+            .get("grpc-status")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        if let Some(code) = code {
+            self.end(Code::from_i32(code));
+        }
+    }
+}
+
+impl Drop for CallRecord {
+    fn drop(&mut self) {
+        // A response that is dropped before its status arrives was cancelled by the caller.
+        let ended = self.ended.lock().unwrap().take();
+        let (code, latency) = ended.unwrap_or_else(|| (Code::Cancelled, self.started.elapsed()));
+        self.recorder.record_call(&CallMetrics {
+            service: &self.service,
+            method: &self.method,
+            attempt: self.attempt,
+            code,
+            latency,
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        });
+    }
+}
+
+/// Whether a [`MeteredBody`] is the body of the request or of the response.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Direction {
+    Sent,
+    Received,
+}
+
+/// A body whose size is counted in the record of its attempt.
+pub(crate) struct MeteredBody<B> {
+    inner: B,
+    record: Option<(Arc<CallRecord>, Direction)>,
+}
+
+impl<B> MeteredBody<B> {
+    pub(crate) fn new(inner: B, record: Option<(Arc<CallRecord>, Direction)>) -> MeteredBody<B> {
+        MeteredBody { inner, record }
+    }
+}
+
+impl<B> HttpBody for MeteredBody<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, B::Error>>> {
+        let data = futures::ready!(Pin::new(&mut self.inner).poll_data(cx));
+        match (&data, &self.record) {
+            (Some(Ok(bytes)), Some((record, direction))) => {
+                let counter = match direction {
+                    Direction::Sent => &record.bytes_sent,
+                    Direction::Received => &record.bytes_received,
+                };
+                counter.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+            }
+            (Some(Err(_)), Some((record, Direction::Received))) => record.end(Code::Unavailable),
+            _ => {}
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, B::Error>> {
+        let trailers = futures::ready!(Pin::new(&mut self.inner).poll_trailers(cx));
+        if let Some((record, Direction::Received)) = &self.record {
+            match &trailers {
+                Ok(Some(trailers)) => record.end_with(trailers),
+                Ok(None) => record.end(Code::Unknown),
+                Err(_) => record.end(Code::Unavailable),
+            }
+        }
+        Poll::Ready(trailers)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{CallRecord, Direction, MeteredBody};
+    use crate::recorder::{CallMetrics, MetricsRecorder};
+    use std::sync::{Arc, Mutex};
+    use tonic::Code;
+
+    #[derive(Default)]
+    struct Calls(Mutex<Vec<(String, u32, Code, u64)>>);
+
+    impl MetricsRecorder for Calls {
+        fn record_call(&self, call: &CallMetrics) {
+            let method = [call.service, call.method].join("/");
+            let call = (method, call.attempt, call.code, call.bytes_received);
+            self.0.lock().unwrap().push(call);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_record() {
+        let calls = Arc::new(Calls::default());
+        let record = CallRecord::start(calls.clone(), "/google.pubsub.v1.Publisher/Publish", 2);
+        let mut headers = http::HeaderMap::new();
+        headers.insert("grpc-status", "14".parse().unwrap());
+        record.end_with(&headers);
+        let body = MeteredBody::new(
+            hyper::Body::from("abc"),
+            Some((record, Direction::Received)),
+        );
+        hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(
+            vec![(
+                "google.pubsub.v1.Publisher/Publish".to_string(),
+                2,
+                Code::Unavailable,
+                3
+            )],
+            *calls.0.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use super::{
+    endpoint::ProxyConnector,
+    metered::{CallRecord, Direction, MeteredBody},
+    ATTEMPT_HEADER, TIMEOUT_HEADER,
+};
+use crate::recorder::{self, MetricsRecorder};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tonic::{
+    body::BoxBody,
+    client::GrpcService,
+    transport::{channel::ResponseFuture, Channel, Endpoint},
+    Code,
+};
+use tower_service::Service;
+
+/// How the channel of each call is picked from the pool.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelSelection {
+    RoundRobin,
+    /// The channel with the fewest calls waiting for a response.
+    LeastLoaded,
+}
+
+/// Settings for the connections of a service.
+#[derive(Debug, Clone)]
+pub struct ChannelSettings {
+    /// The number of channels, each with its own HTTP/2 connection.
+    pub pool_size: usize,
+    pub selection: ChannelSelection,
+    /// The interval of HTTP/2 pings, or `None` to send none.
+    pub keep_alive_interval: Option<Duration>,
+    /// How long to wait for the response to a ping before closing the connection.
+    pub keep_alive_timeout: Option<Duration>,
+    /// Whether to send pings while no call is in flight.
+    pub keep_alive_while_idle: bool,
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        ChannelSettings {
+            pool_size: 1,
+            selection: ChannelSelection::RoundRobin,
+            keep_alive_interval: None,
+            keep_alive_timeout: None,
+            keep_alive_while_idle: false,
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
+        }
+    }
+}
+
+impl ChannelSettings {
+    pub(crate) fn configure(&self, mut endpoint: Endpoint) -> Endpoint {
+        if let Some(interval) = self.keep_alive_interval {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(self.keep_alive_while_idle);
+        }
+        if let Some(timeout) = self.keep_alive_timeout {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+        endpoint
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size)
+    }
+}
+
+/// A channel of the pool, which is replaced once a call fails on its connection.
+struct Slot {
+    channel: Mutex<Channel>,
+    in_flight: AtomicUsize,
+    dead: AtomicBool,
+}
+
+/// Opens the channels of a pool.
+#[derive(Clone)]
+pub(crate) struct Connector {
+    endpoint: Endpoint,
+    proxy: Option<ProxyConnector>,
+}
+
+impl Connector {
+    pub(crate) fn new(endpoint: Endpoint, proxy: Option<ProxyConnector>) -> Connector {
+        Connector { endpoint, proxy }
+    }
+
+    /// Connects lazily, on the first call, unless the connection goes through a proxy.
+    async fn connect(&self) -> Result<Channel, tonic::transport::Error> {
+        match &self.proxy {
+            Some(proxy) => self.endpoint.connect_with_connector(proxy.clone()).await,
+            None => self.endpoint.connect_lazy(),
+        }
+    }
+
+    fn reconnect(&self, slot: Arc<Slot>) {
+        match &self.proxy {
+            Some(_) => {
+                // The dead channel keeps failing fast until the new one has connected.
+                let connector = self.clone();
+                tokio::spawn(async move {
+                    match connector.connect().await {
+                        Ok(channel) => *slot.channel.lock().unwrap() = channel,
+                        Err(_) => slot.dead.store(true, Ordering::SeqCst),
+                    }
+                });
+            }
+            None => {
+                // The endpoint has already been connected lazily once, so this cannot fail.
+                if let Ok(channel) = self.endpoint.connect_lazy() {
+                    *slot.channel.lock().unwrap() = channel;
+                }
+            }
+        }
+    }
+}
+
+struct ChannelPool {
+    connector: Connector,
+    /// The recorder of the client the pool belongs to.
+    recorder: Option<Arc<dyn MetricsRecorder>>,
+    selection: ChannelSelection,
+    slots: Vec<Arc<Slot>>,
+    next: AtomicUsize,
+}
+
+impl ChannelPool {
+    fn select(&self) -> Arc<Slot> {
+        let slot = match self.selection {
+            ChannelSelection::RoundRobin => {
+                let index = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+                &self.slots[index]
+            }
+            ChannelSelection::LeastLoaded => self
+                .slots
+                .iter()
+                .min_by_key(|slot| slot.in_flight.load(Ordering::Relaxed))
+                .unwrap(),
+        };
+        if slot.dead.swap(false, Ordering::SeqCst) {
+            self.connector.reconnect(slot.clone());
+        }
+        slot.clone()
+    }
+}
+
+/// The pooled channels of a service.
+///
+/// Every call picks a channel of the pool, and sends its deadline as the `grpc-timeout` header.
+/// The metrics of every call are recorded when a metrics recorder is set.
+pub(crate) struct Transport {
+    pool: Arc<ChannelPool>,
+    /// The channel picked by `poll_ready` for the next call.
+    ready: Option<(Arc<Slot>, Channel)>,
+}
+
+impl Transport {
+    pub(crate) async fn new(
+        connector: Connector,
+        settings: &ChannelSettings,
+        recorder: Option<Arc<dyn MetricsRecorder>>,
+    ) -> Result<Transport, tonic::transport::Error> {
+        let mut slots = Vec::new();
+        for _ in 0..std::cmp::max(settings.pool_size, 1) {
+            slots.push(Arc::new(Slot {
+                channel: Mutex::new(connector.connect().await?),
+                in_flight: AtomicUsize::new(0),
+                dead: AtomicBool::new(false),
+            }));
+        }
+        let pool = ChannelPool {
+            connector,
+            recorder,
+            selection: settings.selection,
+            slots,
+            next: AtomicUsize::new(0),
+        };
+        Ok(Transport {
+            pool: Arc::new(pool),
+            ready: None,
+        })
+    }
+}
+
+impl Clone for Transport {
+    fn clone(&self) -> Self {
+        Transport {
+            pool: self.pool.clone(),
+            ready: None,
+        }
+    }
+}
+
+impl Service<http::Request<BoxBody>> for Transport {
+    type Response = http::Response<MeteredBody<hyper::Body>>;
+    type Error = tonic::transport::Error;
+    type Future = PooledFuture;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let pool = &self.pool;
+        let (_, channel) = self.ready.get_or_insert_with(|| {
+            let slot = pool.select();
+            let channel = slot.channel.lock().unwrap().clone();
+            (slot, channel)
+        });
+        GrpcService::poll_ready(channel, cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<BoxBody>) -> Self::Future {
+        let (slot, mut channel) = self
+            .ready
+            .take()
+            .expect("poll_ready must be called before call");
+        #[cfg(feature = "tracing")]
+        crate::trace::record_request(&request);
+        let headers = request.headers_mut();
+        if let Some(timeout) = headers.remove(TIMEOUT_HEADER) {
+            headers.insert("grpc-timeout", timeout);
+        }
+        let attempt = headers
+            .remove(ATTEMPT_HEADER)
+            .and_then(|attempt| attempt.to_str().ok()?.parse().ok())
+            .unwrap_or(1);
+        let record = recorder::current(self.pool.recorder.as_ref())
+            .map(|recorder| CallRecord::start(recorder, request.uri().path(), attempt));
+        let request = match &record {
+            Some(record) => request.map(|body| {
+                BoxBody::new(MeteredBody::new(
+                    body,
+                    Some((record.clone(), Direction::Sent)),
+                ))
+            }),
+            None => request,
+        };
+        slot.in_flight.fetch_add(1, Ordering::Relaxed);
+        PooledFuture {
+            inner: GrpcService::call(&mut channel, request),
+            slot,
+            record,
+        }
+    }
+}
+
+/// The response of a pooled call, which counts as in flight on its channel until it resolves.
+pub(crate) struct PooledFuture {
+    inner: ResponseFuture,
+    slot: Arc<Slot>,
+    record: Option<Arc<CallRecord>>,
+}
+
+impl Future for PooledFuture {
+    type Output = Result<http::Response<MeteredBody<hyper::Body>>, tonic::transport::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = futures::ready!(Pin::new(&mut self.inner).poll(cx));
+        let record = self.record.take();
+        match result {
+            Ok(response) => {
+                if let Some(record) = &record {
+                    record.end_with(response.headers());
+                }
+                let record = record.map(|record| (record, Direction::Received));
+                Poll::Ready(Ok(response.map(|body| MeteredBody::new(body, record))))
+            }
+            Err(err) => {
+                self.slot.dead.store(true, Ordering::SeqCst);
+                if let Some(record) = record {
+                    record.end(Code::Unavailable);
+                }
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
+impl Drop for PooledFuture {
+    fn drop(&mut self) {
+        self.slot.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelSelection, ChannelSettings, Connector, Transport};
+    use std::sync::atomic::Ordering;
+    use tonic::transport::Endpoint;
+
+    async fn transport(selection: ChannelSelection) -> Transport {
+        let settings = ChannelSettings {
+            pool_size: 3,
+            selection,
+            ..ChannelSettings::default()
+        };
+        let connector = Connector::new(Endpoint::from_static("http://localhost:1"), None);
+        Transport::new(connector, &settings, None).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let pool = transport(ChannelSelection::RoundRobin).await.pool;
+        let selected: Vec<_> = (0..4).map(|_| pool.select()).collect();
+        assert!(std::sync::Arc::ptr_eq(&selected[0], &selected[3]));
+        assert!(!std::sync::Arc::ptr_eq(&selected[0], &selected[1]));
+        assert!(!std::sync::Arc::ptr_eq(&selected[1], &selected[2]));
+    }
+
+    #[tokio::test]
+    async fn test_least_loaded() {
+        let pool = transport(ChannelSelection::LeastLoaded).await.pool;
+        pool.slots[0].in_flight.store(2, Ordering::Relaxed);
+        pool.slots[1].in_flight.store(1, Ordering::Relaxed);
+        pool.slots[2].in_flight.store(3, Ordering::Relaxed);
+        assert!(std::sync::Arc::ptr_eq(&pool.slots[1], &pool.select()));
+    }
+
+    #[tokio::test]
+    async fn test_dead_channel() {
+        let pool = transport(ChannelSelection::RoundRobin).await.pool;
+        pool.slots[0].dead.store(true, Ordering::SeqCst);
+        pool.select();
+        assert!(!pool.slots[0].dead.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,276 @@
+//! Spans for every RPC, recorded when the `tracing` feature is enabled.
+//!
+//! Each attempt of a call gets a `grpc_gcp.rpc` span with the service, method, project, resource
+//! name, attempt number, status code and latency of the attempt, and sends a W3C `traceparent`
+//! header so that Cloud Trace can link it to the spans of the server. The number of every attempt
+//! is also passed to the transport, which records it with the metrics of the attempt.
+//!
+//! The trace of the header is the one of a `traceparent` set by the caller, or with the `otel`
+//! feature, the OpenTelemetry context of the span, which is a child of the current span. The
+//! header is only marked as sampled when that trace is, or else when the span is recorded.
+
+use crate::service::ATTEMPT_HEADER;
+use std::future::Future;
+use tonic::{metadata::MetadataValue, Request, Response, Status};
+
+#[cfg(feature = "tracing")]
+use crate::options::ROUTING_HEADER;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use tonic::Code;
+#[cfg(feature = "tracing")]
+use tracing::{field, Instrument};
+#[cfg(feature = "otel")]
+use {opentelemetry::trace::TraceContextExt, tracing_opentelemetry::OpenTelemetrySpanExt};
+
+#[cfg(feature = "tracing")]
+const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// The trace that the attempts of a call belong to.
+#[derive(Clone, Copy)]
+pub(crate) struct Trace {
+    #[cfg(feature = "tracing")]
+    trace_id: u128,
+}
+
+impl Trace {
+    pub(crate) fn new() -> Trace {
+        Trace {
+            #[cfg(feature = "tracing")]
+            trace_id: rand::random(),
+        }
+    }
+
+    /// Sends `request` with `send`, as the attempt number `attempt` of the call.
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) async fn attempt<R, T, F, Fut>(
+        &self,
+        attempt: u32,
+        mut request: Request<R>,
+        send: F,
+    ) -> Result<Response<T>, Status>
+    where
+        F: FnOnce(Request<R>) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        set_attempt(&mut request, attempt);
+        send(request).await
+    }
+
+    /// Sends `request` with `send`, as the attempt number `attempt` of the call.
+    #[cfg(feature = "tracing")]
+    pub(crate) async fn attempt<R, T, F, Fut>(
+        &self,
+        attempt: u32,
+        mut request: Request<R>,
+        send: F,
+    ) -> Result<Response<T>, Status>
+    where
+        F: FnOnce(Request<R>) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        set_attempt(&mut request, attempt);
+        let span = tracing::info_span!(
+            "grpc_gcp.rpc",
+            service = field::Empty,
+            method = field::Empty,
+            project = field::Empty,
+            resource = field::Empty,
+            attempt,
+            code = field::Empty,
+            latency_ms = field::Empty,
+            trace_id = field::Empty,
+            span_id = field::Empty,
+        );
+
+        let metadata = request.metadata_mut();
+        let parent = metadata
+            .get(TRACEPARENT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(SpanContext::parse);
+        let context = SpanContext::of(&span, parent, self.trace_id);
+        span.record(
+            "trace_id",
+            &field::display(format_args!("{:032x}", context.trace_id)),
+        );
+        span.record(
+            "span_id",
+            &field::display(format_args!("{:016x}", context.span_id)),
+        );
+        metadata.insert(
+            TRACEPARENT_HEADER,
+            MetadataValue::from_str(&context.traceparent()).unwrap(),
+        );
+
+        let started = Instant::now();
+        let result = send(request).instrument(span.clone()).await;
+        let code = match &result {
+            Ok(_) => Code::Ok,
+            Err(status) => status.code(),
+        };
+        span.record("code", &field::debug(code));
+        span.record("latency_ms", &(started.elapsed().as_millis() as u64));
+        result
+    }
+}
+
+/// Sends `request` with `send` as a call of a single attempt.
+pub(crate) async fn send<R, T, F, Fut>(request: Request<R>, send: F) -> Result<Response<T>, Status>
+where
+    F: FnOnce(Request<R>) -> Fut,
+    Fut: Future<Output = Result<Response<T>, Status>>,
+{
+    Trace::new().attempt(1, request, send).await
+}
+
+/// Tells the transport, which records the metrics of the call, the number of the attempt.
+fn set_attempt<R>(request: &mut Request<R>, attempt: u32) {
+    let attempt = MetadataValue::from_str(&attempt.to_string()).unwrap();
+    request.metadata_mut().insert(ATTEMPT_HEADER, attempt);
+}
+
+/// Records the service and method of `request`, and the resource in its routing header, on the
+/// span of the attempt it is sent by.
+#[cfg(feature = "tracing")]
+pub(crate) fn record_request<B>(request: &http::Request<B>) {
+    let span = tracing::Span::current();
+    let mut path = request.uri().path().trim_start_matches('/').splitn(2, '/');
+    if let (Some(service), Some(method)) = (path.next(), path.next()) {
+        span.record("service", &service);
+        span.record("method", &method);
+    }
+    let params = request
+        .headers()
+        .get(ROUTING_HEADER)
+        .and_then(|value| value.to_str().ok());
+    for (key, value) in url::form_urlencoded::parse(params.unwrap_or("").as_bytes()) {
+        if key == "project_id" {
+            span.record("project", &value.as_ref());
+            continue;
+        }
+        span.record("resource", &value.as_ref());
+        if let Some(project) = value
+            .strip_prefix("projects/")
+            .and_then(|rest| rest.split('/').next())
+        {
+            span.record("project", &project);
+        }
+    }
+}
+
+/// The W3C trace context of an attempt.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SpanContext {
+    trace_id: u128,
+    span_id: u64,
+    sampled: bool,
+}
+
+#[cfg(feature = "tracing")]
+impl SpanContext {
+    /// The context of the attempt recorded by `span`, in the trace of `parent`, or else of the
+    /// OpenTelemetry context of `span`, or else of `trace_id`.
+    fn of(span: &tracing::Span, parent: Option<SpanContext>, trace_id: u128) -> SpanContext {
+        #[cfg(feature = "otel")]
+        {
+            let context = span.context();
+            let context = context.span().span_context();
+            if parent.is_none() && context.is_valid() {
+                return SpanContext {
+                    trace_id: context.trace_id().to_u128(),
+                    span_id: context.span_id().to_u64(),
+                    sampled: context.is_sampled(),
+                };
+            }
+        }
+        SpanContext {
+            trace_id: parent.map_or(trace_id, |parent| parent.trace_id),
+            span_id: rand::random::<u64>() | 1,
+            sampled: parent.map_or(!span.is_disabled(), |parent| parent.sampled),
+        }
+    }
+
+    fn parse(traceparent: &str) -> Option<SpanContext> {
+        let mut parts = traceparent.split('-');
+        let _version = parts.next()?;
+        let trace_id = parts.next().filter(|id| id.len() == 32)?;
+        let span_id = parts.next().filter(|id| id.len() == 16)?;
+        let flags = parts.next().filter(|flags| flags.len() == 2)?;
+        Some(SpanContext {
+            trace_id: u128::from_str_radix(trace_id, 16)
+                .ok()
+                .filter(|&id| id != 0)?,
+            span_id: u64::from_str_radix(span_id, 16).ok()?,
+            sampled: u8::from_str_radix(flags, 16).ok()? & 1 == 1,
+        })
+    }
+
+    fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.sampled as u8
+        )
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::{SpanContext, Trace, TRACEPARENT_HEADER};
+    use tonic::{metadata::MetadataValue, Request, Response};
+
+    #[test]
+    fn test_traceparent() {
+        let context = SpanContext {
+            trace_id: 0x0af7651916cd43dd8448eb211c80319c,
+            span_id: 0xb7ad6b7169203331,
+            sampled: true,
+        };
+        let header = context.traceparent();
+        assert_eq!(
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            header
+        );
+        assert_eq!(Some(context), SpanContext::parse(&header));
+        let unsampled =
+            SpanContext::parse("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00");
+        assert!(!unsampled.unwrap().sampled);
+        assert_eq!(None, SpanContext::parse("00-invalid-b7ad6b7169203331-01"));
+    }
+
+    #[tokio::test]
+    async fn test_parent() {
+        let parent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00";
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert(TRACEPARENT_HEADER, MetadataValue::from_static(parent));
+        let sent = |request: Request<()>| async move {
+            let header = request.metadata().get(TRACEPARENT_HEADER).unwrap();
+            Ok(Response::new(header.to_str().unwrap().to_string()))
+        };
+        let header = Trace::new().attempt(1, request, sent).await.unwrap();
+        let context = SpanContext::parse(header.get_ref()).unwrap();
+        assert_eq!(0x0af7651916cd43dd8448eb211c80319c, context.trace_id);
+        assert_ne!(0xb7ad6b7169203331, context.span_id);
+        assert!(!context.sampled);
+    }
+
+    #[tokio::test]
+    async fn test_attempt() {
+        let trace = Trace::new();
+        let sent = |request: Request<()>| async move {
+            let header = request.metadata().get(TRACEPARENT_HEADER).unwrap();
+            Ok(Response::new(header.to_str().unwrap().to_string()))
+        };
+        let first = trace.attempt(1, Request::new(()), sent).await.unwrap();
+        let second = trace.attempt(2, Request::new(()), sent).await.unwrap();
+        let first = SpanContext::parse(first.get_ref()).unwrap();
+        let second = SpanContext::parse(second.get_ref()).unwrap();
+        assert_eq!(first.trace_id, second.trace_id);
+        assert_ne!(first, second);
+    }
+}
@@ -1 +1,2 @@
 pub(crate) mod init_once;
+pub(crate) mod time;
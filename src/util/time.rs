@@ -0,0 +1,70 @@
+use std::{
+    convert::TryFrom,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub(crate) fn to_proto_duration(duration: Duration) -> prost_types::Duration {
+    prost_types::Duration {
+        seconds: i64::try_from(duration.as_secs()).unwrap_or(i64::MAX),
+        nanos: duration.subsec_nanos() as i32,
+    }
+}
+
+pub(crate) fn from_proto_duration(duration: prost_types::Duration) -> Duration {
+    if duration.seconds < 0 || duration.nanos < 0 {
+        return Duration::from_secs(0);
+    }
+    Duration::new(duration.seconds as u64, duration.nanos as u32)
+}
+
+pub(crate) fn to_proto_timestamp(time: SystemTime) -> prost_types::Timestamp {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => prost_types::Timestamp {
+            seconds: i64::try_from(duration.as_secs()).unwrap_or(i64::MAX),
+            nanos: duration.subsec_nanos() as i32,
+        },
+        Err(err) => {
+            let duration = err.duration();
+            let mut seconds = -i64::try_from(duration.as_secs()).unwrap_or(i64::MAX);
+            let mut nanos = -(duration.subsec_nanos() as i32);
+            if nanos < 0 {
+                seconds -= 1;
+                nanos += 1_000_000_000;
+            }
+            prost_types::Timestamp { seconds, nanos }
+        }
+    }
+}
+
+pub(crate) fn from_proto_timestamp(timestamp: prost_types::Timestamp) -> SystemTime {
+    let nanos = Duration::from_nanos(timestamp.nanos.max(0) as u64);
+    if timestamp.seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(timestamp.seconds as u64) + nanos
+    } else {
+        UNIX_EPOCH - Duration::from_secs((-timestamp.seconds) as u64) + nanos
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{from_proto_timestamp, to_proto_timestamp};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::new(1609200000, 100000000);
+        let timestamp = to_proto_timestamp(time);
+        assert_eq!(
+            (1609200000, 100000000),
+            (timestamp.seconds, timestamp.nanos)
+        );
+        assert_eq!(time, from_proto_timestamp(timestamp));
+
+        let time = UNIX_EPOCH - Duration::new(1, 250000000);
+        let timestamp = to_proto_timestamp(time);
+        assert_eq!((-2, 750000000), (timestamp.seconds, timestamp.nanos));
+        assert_eq!(time, from_proto_timestamp(timestamp));
+    }
+}
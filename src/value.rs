@@ -0,0 +1,643 @@
+//! A dynamically typed value of Datastore and Firestore, for data without a fixed schema.
+
+use crate::{
+    datastore::v1::Key,
+    proto::google::{
+        datastore::{self, v1::key::path_element::IdType},
+        firestore,
+    },
+    serde_properties::{ArrayValueTrait, LatLngTrait, MapValueTrait, ValueTrait, ValueType},
+    util::time::{from_proto_timestamp, to_proto_timestamp},
+    Error,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::SerializeStruct,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt::{self, Write},
+    ops::Index,
+    time::SystemTime,
+};
+
+/// The name by which [`Value`] asks the deserializer of the crate for the types that serde has no
+/// counterpart of.
+pub(crate) const VALUE_TOKEN: &str = "$__grpc_gcp_private_Value";
+pub(crate) const TIMESTAMP_TOKEN: &str = "$__grpc_gcp_private_Timestamp";
+pub(crate) const GEO_POINT_TOKEN: &str = "$__grpc_gcp_private_GeoPoint";
+pub(crate) const REFERENCE_TOKEN: &str = "$__grpc_gcp_private_Reference";
+pub(crate) const KEY_TOKEN: &str = "$__grpc_gcp_private_Key";
+
+static NULL: Value = Value::Null;
+
+/// A value of a Datastore entity or a Firestore document.
+///
+/// Reading a `Value` with `Datastore::get` or `DocumentReference::get` keeps the type of every
+/// field, such as timestamps and references, which a concrete type would read as maps and strings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Double(f64),
+    Timestamp(SystemTime),
+    String(String),
+    Bytes(Vec<u8>),
+    /// The name of a Firestore document, such as `projects/p/databases/(default)/documents/c/d`.
+    Reference(String),
+    GeoPoint(GeoPoint),
+    /// A Datastore key.
+    Key(Key),
+    Array(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Value {
+    /// Returns the value at `path`, whose segments are separated by dots.
+    ///
+    /// A segment is the name of a field of a map, or the index of an element of an array. Field
+    /// names that contain dots are quoted with backticks, as in Firestore field paths, such as
+    /// ``a.`b.c`.0``.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        field_path(path)
+            .iter()
+            .try_fold(self, |value, segment| match value {
+                Value::Map(fields) => fields.get(segment),
+                Value::Array(values) => segment.parse().ok().and_then(|i: usize| values.get(i)),
+                _ => None,
+            })
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    pub(crate) fn from_proto<V: ValueTrait>(value: V) -> Value {
+        let value_type = match value.into_value_type() {
+            Some(value_type) => value_type,
+            None => return Value::Null,
+        };
+        match value_type {
+            ValueType::NullValue(_) => Value::Null,
+            ValueType::BooleanValue(value) => Value::Bool(value),
+            ValueType::IntegerValue(value) => Value::Integer(value),
+            ValueType::DoubleValue(value) => Value::Double(value),
+            ValueType::TimestampValue(value) => Value::Timestamp(from_proto_timestamp(value)),
+            ValueType::StringValue(value) => Value::String(value),
+            ValueType::BytesValue(value) => Value::Bytes(value),
+            ValueType::ReferenceValue(value) => Value::Reference(value),
+            ValueType::GeoPointValue(value) => Value::GeoPoint(GeoPoint {
+                latitude: value.get_latitude(),
+                longitude: value.get_longitude(),
+            }),
+            ValueType::ArrayValue(value) => {
+                let values = value.get_values().into_iter().map(Value::from_proto);
+                Value::Array(values.collect())
+            }
+            ValueType::MapValue(value) => Value::from_fields(value.get_fields()),
+            ValueType::KeyValue(value) => Value::Key(Key(value)),
+        }
+    }
+
+    /// Converts the fields of an entity or a document into a [`Value::Map`].
+    pub(crate) fn from_fields<V: ValueTrait>(fields: HashMap<String, V>) -> Value {
+        let fields = fields
+            .into_iter()
+            .map(|(name, value)| (name, Value::from_proto(value)));
+        Value::Map(fields.collect())
+    }
+
+    pub(crate) fn into_proto<V: ValueTrait>(self) -> Result<V, Error> {
+        let name = self.type_name();
+        let value_type = match self {
+            Value::Null => ValueType::NullValue(0),
+            Value::Bool(value) => ValueType::BooleanValue(value),
+            Value::Integer(value) => ValueType::IntegerValue(value),
+            Value::Double(value) => ValueType::DoubleValue(value),
+            Value::Timestamp(value) => ValueType::TimestampValue(to_proto_timestamp(value)),
+            Value::String(value) => ValueType::StringValue(value),
+            Value::Bytes(value) => ValueType::BytesValue(value),
+            Value::Reference(value) => ValueType::ReferenceValue(value),
+            Value::GeoPoint(value) => {
+                ValueType::GeoPointValue(V::LatLng::new(value.latitude, value.longitude))
+            }
+            Value::Key(value) => ValueType::KeyValue(value.0),
+            Value::Array(values) => {
+                let values = values.into_iter().map(Value::into_proto);
+                ValueType::ArrayValue(V::ArrayValue::new(values.collect::<Result<_, _>>()?))
+            }
+            Value::Map(fields) => ValueType::MapValue(V::MapValue::new(into_proto_fields(fields)?)),
+        };
+        V::try_new(value_type).ok_or_else(|| Error::UnsupportedValue(name.to_string()))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "Null",
+            Value::Bool(_) => "Bool",
+            Value::Integer(_) => "Integer",
+            Value::Double(_) => "Double",
+            Value::Timestamp(_) => "Timestamp",
+            Value::String(_) => "String",
+            Value::Bytes(_) => "Bytes",
+            Value::Reference(_) => "Reference",
+            Value::GeoPoint(_) => "GeoPoint",
+            Value::Key(_) => "Key",
+            Value::Array(_) => "Array",
+            Value::Map(_) => "Map",
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Double(value) => write!(f, "{:?}", value),
+            Value::Timestamp(value) => {
+                let time = DateTime::<Utc>::from(*value);
+                write!(
+                    f,
+                    "Timestamp({})",
+                    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                )
+            }
+            Value::String(value) => write!(f, "{:?}", value),
+            Value::Bytes(value) => write!(f, "Bytes({})", base64::encode(value)),
+            Value::Reference(value) => write!(f, "Reference({})", value),
+            Value::GeoPoint(value) => {
+                write!(f, "GeoPoint({:?}, {:?})", value.latitude, value.longitude)
+            }
+            Value::Key(key) => {
+                f.write_str("Key(")?;
+                for (i, element) in key.0.path.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match &element.id_type {
+                        Some(IdType::Id(id)) => write!(f, "{}, {}", element.kind, id)?,
+                        Some(IdType::Name(name)) => write!(f, "{}, {:?}", element.kind, name)?,
+                        None => f.write_str(&element.kind)?,
+                    }
+                }
+                f.write_char(')')
+            }
+            Value::Array(values) => {
+                let entries = values.iter().map(|value| (None, value));
+                write_entries(f, indent, ('[', ']'), entries)
+            }
+            Value::Map(fields) => {
+                let entries = fields.iter().map(|(name, value)| (Some(name), value));
+                write_entries(f, indent, ('{', '}'), entries)
+            }
+        }
+    }
+}
+
+impl From<datastore::v1::Value> for Value {
+    fn from(value: datastore::v1::Value) -> Value {
+        Value::from_proto(value)
+    }
+}
+
+impl From<firestore::v1::Value> for Value {
+    fn from(value: firestore::v1::Value) -> Value {
+        Value::from_proto(value)
+    }
+}
+
+impl TryFrom<Value> for datastore::v1::Value {
+    type Error = Error;
+
+    /// Fails for references, which Datastore cannot hold.
+    fn try_from(value: Value) -> Result<Self, Error> {
+        value.into_proto()
+    }
+}
+
+impl TryFrom<Value> for firestore::v1::Value {
+    type Error = Error;
+
+    /// Fails for keys, which Firestore cannot hold.
+    fn try_from(value: Value) -> Result<Self, Error> {
+        value.into_proto()
+    }
+}
+
+fn into_proto_fields<V: ValueTrait>(
+    fields: BTreeMap<String, Value>,
+) -> Result<HashMap<String, V>, Error> {
+    fields
+        .into_iter()
+        .map(|(name, value)| Ok((name, value.into_proto()?)))
+        .collect()
+}
+
+/// Splits a field path into its segments, removing the backticks that quote them.
+fn field_path(path: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut segment = String::new();
+    let mut quoted = false;
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => quoted = !quoted,
+            '\\' if quoted => segment.extend(chars.next()),
+            '.' if !quoted => segments.push(std::mem::take(&mut segment)),
+            c => segment.push(c),
+        }
+    }
+    segments.push(segment);
+    segments
+}
+
+fn write_entries<'a>(
+    f: &mut fmt::Formatter,
+    indent: Option<usize>,
+    (open, close): (char, char),
+    entries: impl ExactSizeIterator<Item = (Option<&'a String>, &'a Value)>,
+) -> fmt::Result {
+    if entries.len() == 0 {
+        f.write_char(open)?;
+        return f.write_char(close);
+    }
+    f.write_char(open)?;
+    for (i, (name, value)) in entries.enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        match indent {
+            Some(indent) => write!(f, "\n{:width$}", "", width = (indent + 1) * 2)?,
+            None if i > 0 => f.write_char(' ')?,
+            None => {}
+        }
+        if let Some(name) = name {
+            write!(f, "{:?}: ", name)?;
+        }
+        value.write(f, indent.map(|indent| indent + 1))?;
+    }
+    if let Some(indent) = indent {
+        write!(f, "\n{:width$}", "", width = indent * 2)?;
+    }
+    f.write_char(close)
+}
+
+/// Prints the value on one line, or indented over several lines with `{:#}`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = if f.alternate() { Some(0) } else { None };
+        self.write(f, indent)
+    }
+}
+
+impl<'a> Index<&'a str> for Value {
+    type Output = Value;
+
+    /// Returns the value at the field path `path`, or [`Value::Null`] if there is none.
+    fn index(&self, path: &'a str) -> &Value {
+        self.get(path).unwrap_or(&NULL)
+    }
+}
+
+/// Serializes timestamps, geopoints and keys as the maps that the deserializer of the crate reads
+/// them as, and references as strings.
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            Value::Integer(value) => serializer.serialize_i64(*value),
+            Value::Double(value) => serializer.serialize_f64(*value),
+            Value::Timestamp(value) => {
+                let timestamp = to_proto_timestamp(*value);
+                let mut state = serializer.serialize_struct("Timestamp", 2)?;
+                state.serialize_field("seconds", &timestamp.seconds)?;
+                state.serialize_field("nanos", &timestamp.nanos)?;
+                state.end()
+            }
+            Value::String(value) | Value::Reference(value) => serializer.serialize_str(value),
+            Value::Bytes(value) => serializer.serialize_bytes(value),
+            Value::GeoPoint(value) => value.serialize(serializer),
+            Value::Key(key) => key_fields(key).serialize(serializer),
+            Value::Array(values) => serializer.collect_seq(values),
+            Value::Map(fields) => serializer.collect_map(fields),
+        }
+    }
+}
+
+/// The fields of a key, as the deserializer of the crate reads it.
+fn key_fields(key: &Key) -> Value {
+    let map = |fields: Vec<(&str, Value)>| {
+        let fields = fields
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value));
+        Value::Map(fields.collect())
+    };
+    let partition_id = match &key.0.partition_id {
+        Some(partition_id) => map(vec![
+            ("project_id", Value::String(partition_id.project_id.clone())),
+            (
+                "namespace_id",
+                Value::String(partition_id.namespace_id.clone()),
+            ),
+        ]),
+        None => Value::Null,
+    };
+    let path = key.0.path.iter().map(|element| {
+        let id_type = match &element.id_type {
+            Some(IdType::Id(id)) => map(vec![("Id", Value::Integer(*id))]),
+            Some(IdType::Name(name)) => map(vec![("Name", Value::String(name.clone()))]),
+            None => Value::Null,
+        };
+        map(vec![
+            ("kind", Value::String(element.kind.clone())),
+            ("id_type", id_type),
+        ])
+    });
+    map(vec![
+        ("partition_id", partition_id),
+        ("path", Value::Array(path.collect())),
+    ])
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_newtype_struct(VALUE_TOKEN, ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Integer(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        match i64::try_from(value) {
+            Ok(value) => Ok(Value::Integer(value)),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Unsigned(value), &self)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::Double(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(value))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut fields = BTreeMap::new();
+        let name: String = match map.next_key()? {
+            Some(name) => name,
+            None => return Ok(Value::Map(fields)),
+        };
+        match name.as_str() {
+            TIMESTAMP_TOKEN => {
+                let (seconds, nanos): (i64, i64) = map.next_value()?;
+                let timestamp = prost_types::Timestamp {
+                    seconds,
+                    nanos: nanos as i32,
+                };
+                return Ok(Value::Timestamp(from_proto_timestamp(timestamp)));
+            }
+            GEO_POINT_TOKEN => {
+                let (latitude, longitude) = map.next_value()?;
+                return Ok(Value::GeoPoint(GeoPoint {
+                    latitude,
+                    longitude,
+                }));
+            }
+            REFERENCE_TOKEN => return Ok(Value::Reference(map.next_value()?)),
+            KEY_TOKEN => {
+                let bytes: Vec<u8> = map.next_value()?;
+                let key = prost::Message::decode(bytes.as_slice()).map_err(de::Error::custom)?;
+                return Ok(Value::Key(Key(key)));
+            }
+            _ => {}
+        }
+        fields.insert(name, map.next_value()?);
+        while let Some((name, value)) = map.next_entry()? {
+            fields.insert(name, value);
+        }
+        Ok(Value::Map(fields))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{GeoPoint, Value};
+    use crate::{
+        datastore::v1::Key,
+        proto::google::{datastore, firestore},
+        serde_properties::{deserializer::deserialize, ValueTrait, ValueType},
+        Error,
+    };
+    use std::{
+        collections::{BTreeMap, HashMap},
+        iter::FromIterator,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    fn key() -> Key {
+        use datastore::v1::key::{path_element::IdType, PathElement};
+        let element = |kind: &str, id_type| PathElement {
+            kind: kind.into(),
+            id_type: Some(id_type),
+        };
+        Key(datastore::v1::Key {
+            partition_id: None,
+            path: vec![
+                element("Parent", IdType::Name("a".into())),
+                element("Child", IdType::Id(1)),
+            ],
+        })
+    }
+
+    fn document() -> Value {
+        Value::Map(BTreeMap::from_iter(vec![
+            ("null".into(), Value::Null),
+            ("bool".into(), Value::Bool(true)),
+            ("integer".into(), Value::Integer(-1)),
+            ("double".into(), Value::Double(0.5)),
+            (
+                "timestamp".into(),
+                Value::Timestamp(UNIX_EPOCH + Duration::new(1609200000, 100)),
+            ),
+            ("string".into(), Value::String("a".into())),
+            ("bytes".into(), Value::Bytes(vec![0, 1, 2])),
+            (
+                "geo".into(),
+                Value::GeoPoint(GeoPoint {
+                    latitude: 35.6,
+                    longitude: 139.7,
+                }),
+            ),
+            (
+                "array".into(),
+                Value::Array(vec![Value::Integer(1), Value::Array(vec![])]),
+            ),
+            (
+                "map".into(),
+                Value::Map(BTreeMap::from_iter(vec![("a.b".into(), Value::Integer(2))])),
+            ),
+        ]))
+    }
+
+    fn with(document: &Value, name: &str, value: Value) -> Value {
+        let mut document = document.clone();
+        if let Value::Map(fields) = &mut document {
+            fields.insert(name.into(), value);
+        }
+        document
+    }
+
+    #[test]
+    fn test_proto() {
+        let reference = Value::Reference("projects/p/databases/(default)/documents/c/d".into());
+        let document = with(&document(), "reference", reference.clone());
+        let proto: firestore::v1::Value = document.clone().into_proto().unwrap();
+        assert_eq!(document, Value::from_proto(proto));
+
+        let entity = with(&self::document(), "key", Value::Key(key()));
+        let proto: datastore::v1::Value = entity.clone().into_proto().unwrap();
+        assert_eq!(entity, Value::from_proto(proto));
+
+        match reference.into_proto::<datastore::v1::Value>() {
+            Err(Error::UnsupportedValue(name)) => assert_eq!("Reference", name),
+            result => panic!("{:?}", result),
+        }
+        assert!(Value::Key(key())
+            .into_proto::<firestore::v1::Value>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_get() {
+        let document = document();
+        assert_eq!(Some(&Value::Integer(-1)), document.get("integer"));
+        assert_eq!(Value::Integer(1), document["array.0"]);
+        assert_eq!(Value::Array(vec![]), document["array.1"]);
+        assert_eq!(Value::Integer(2), document["map.`a.b`"]);
+        assert_eq!(None, document.get("map.a"));
+        assert_eq!(None, document.get("array.2"));
+        assert!(document["integer.a"].is_null());
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let entity = with(&document(), "key", Value::Key(key()));
+        let fields = match entity.clone().into_proto::<datastore::v1::Value>() {
+            Ok(value) => value.map_value().unwrap(),
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(entity, deserialize::<Value, _>(fields).unwrap());
+
+        let reference = Value::Reference("projects/p/databases/(default)/documents/c/d".into());
+        let fields: HashMap<String, firestore::v1::Value> = HashMap::from_iter(vec![
+            ("reference".into(), reference.clone().into_proto().unwrap()),
+            ("empty".into(), ValueTrait::new(ValueType::NullValue(0))),
+        ]);
+        #[derive(serde::Deserialize)]
+        struct Holder {
+            reference: Value,
+            empty: Option<Value>,
+        }
+        let holder: Holder = deserialize(fields).unwrap();
+        assert_eq!(reference, holder.reference);
+        assert_eq!(None, holder.empty);
+
+        let json = serde_json::json!({"a": [1, null, "b"], "c": {"d": 0.5}});
+        let value: Value = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(Value::Integer(1), value["a.0"]);
+        assert_eq!(json, serde_json::to_value(&value).unwrap());
+    }
+
+    #[test]
+    fn test_display() {
+        let entity = with(&document(), "key", Value::Key(key()));
+        assert_eq!(
+            concat!(
+                r#"{"array": [1, []], "bool": true, "bytes": Bytes(AAEC), "double": 0.5, "#,
+                r#""geo": GeoPoint(35.6, 139.7), "integer": -1, "key": Key(Parent, "a", Child, 1), "#,
+                r#""map": {"a.b": 2}, "null": null, "string": "a", "#,
+                r#""timestamp": Timestamp(2020-12-29T00:00:00.000000100Z)}"#
+            ),
+            entity.to_string()
+        );
+        let value = Value::Map(BTreeMap::from_iter(vec![
+            (
+                "a".into(),
+                Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+            ),
+            ("b".into(), Value::Map(BTreeMap::new())),
+        ]));
+        assert_eq!(
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {}\n}",
+            format!("{:#}", value)
+        );
+    }
+}